@@ -1,24 +1,92 @@
+use std::{collections::HashSet, time::Instant};
+
+use ratatui::layout::Rect;
+
+use crate::{
+    config::config,
+    form::{BatchField, TaskForm},
+    input::Input,
+    keymap::{Action, Keymap},
+    state::State,
+    tabs::Tab,
+    theme::{self, Theme, ThemeSpec, DEFAULT_THEME},
+    watch::Conflict,
+    Id, Result,
+};
+
 pub struct App {
     pub status: Status,
+    /// colors used to draw the app
+    pub theme: Theme,
+    /// name of the theme, as given in the configuration or with `--theme`
+    pub theme_name: String,
+    /// keys bound to the actions in the task list
+    pub keymap: Keymap,
+    /// index of the first task shown in the list
+    pub offset: usize,
+    /// number of tasks which fit in the list, known once it is drawn
+    pub page: usize,
+    /// last search, its matches are highlighted and jumped to with `n` and `N`
+    pub search: Option<String>,
+    /// whether the details of the selected task are shown next to the list
+    pub details: bool,
+    /// tabs of the task list
+    pub tabs: Vec<Tab>,
+    /// index of the tab shown
+    pub tab: usize,
+    /// task selected in each tab, selected again when going back to it
+    pub tab_selection: Vec<Option<Id>>,
+    /// in visual mode, the task where the marked range starts along with the tasks marked before
+    pub visual: Option<(Id, HashSet<Id>)>,
+    /// rows of the task list, known once it is drawn
+    pub list_area: Rect,
+    /// title of each tab, known once they are drawn
+    pub tab_areas: Vec<Rect>,
+    /// actions of the footer along with where they are drawn
+    pub buttons: Vec<(Rect, Action)>,
+    /// when and on which task the last click was, to tell double clicks
+    pub last_click: Option<(Instant, Id)>,
+    /// message shown in the footer until the next key or click
+    pub notice: Option<String>,
 }
 
 /// The current status of application
 pub enum Status {
     /// Editing state
     ///
-    /// edit stores the buffer value for the new task
+    /// edit stores the fields of the task being typed
     ///
     /// previous tells if we are editing a task or creating new one
     Editing {
-        edit: String,
+        edit: Box<TaskForm>,
         previous: Option<usize>,
     },
     /// Idle state
     Idle,
+    /// Typing a search, the list only shows the matching tasks
+    ///
+    /// query is the text being searched for
+    Searching { query: Input },
+    /// Typing a field to set on the marked tasks
+    ///
+    /// error tells why the text could not be applied
+    Prompt {
+        field: BatchField,
+        input: Input,
+        error: Option<String>,
+    },
+    /// Browsing the deleted tasks
+    ///
+    /// selected is the index of highlighted task in the trash
+    Trash { selected: usize },
     /// Exiting state
     ///
     /// This is to avoid abrupt closure and ask before exit
     Exiting,
+    /// Picking which version to keep of the tasks changed both here and in the data file
+    ///
+    /// conflicts holds the tasks left to settle, the first one is shown
+    Conflict { conflicts: Vec<Conflict> },
 }
 
 impl App {
@@ -26,33 +94,155 @@ impl App {
     pub fn new() -> Self {
         Self {
             status: Status::Idle,
+            theme: Theme::default(),
+            theme_name: DEFAULT_THEME.to_string(),
+            keymap: Keymap::new(&config().keys),
+            offset: 0,
+            page: 1,
+            search: None,
+            details: false,
+            tabs: Tab::all(),
+            tab: 0,
+            tab_selection: vec![None; Tab::all().len()],
+            visual: None,
+            list_area: Rect::default(),
+            tab_areas: Vec::new(),
+            buttons: Vec::new(),
+            last_click: None,
+            notice: None,
         }
     }
-    /// Change status of the app
-    pub fn switch_status(&mut self, new_status: Status) {
-        self.status = new_status;
+
+    /// Use a theme, the colors set in the configuration are applied on top of it
+    pub fn set_theme(&mut self, name: &str) -> Result<()> {
+        let spec = ThemeSpec {
+            name: Some(name.to_string()),
+            ..config().theme.clone()
+        };
+        self.theme = spec.resolve()?;
+        self.theme_name = name.to_string();
+        Ok(())
     }
 
-    /// Add a character to the current task being added/changed
-    pub fn add_char(&mut self, ch: char) {
-        if let Status::Editing { edit, previous: _ } = &mut self.status {
-            edit.push(ch);
+    /// Switch to the theme after the current one, skipping themes which fail to load
+    pub fn next_theme(&mut self) {
+        let names = theme::available();
+        let current = names.iter().position(|name| *name == self.theme_name);
+        let start = current.map_or(0, |idx| idx + 1);
+        for offset in 0..names.len() {
+            if self
+                .set_theme(&names[(start + offset) % names.len()])
+                .is_ok()
+            {
+                return;
+            }
         }
     }
+    /// Indices in `State::ids` of the tasks shown in the current tab
+    pub fn visible(&self, state: &State) -> Vec<usize> {
+        let tab = &self.tabs[self.tab];
+        state
+            .ids
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| {
+                state
+                    .tasks
+                    .get(id)
+                    .is_some_and(|item| tab.matches(&item.task))
+            })
+            .map(|(idx, _)| idx)
+            .collect()
+    }
 
-    /// Remove a charecter fromt he current task being added/changed
-    pub fn pop_char(&mut self) {
-        if let Status::Editing { edit, previous: _ } = &mut self.status {
-            edit.pop();
+    /// Show another tab, selecting the task which was selected in it
+    pub fn switch_tab(&mut self, tab: usize, state: &mut State) {
+        if tab >= self.tabs.len() {
+            return;
+        }
+        self.tab_selection[self.tab] = state.cursor.and_then(|idx| state.ids.get(idx)).copied();
+        self.tab = tab;
+        self.offset = 0;
+        match self.tab_selection[tab].and_then(|id| state.ids.iter().position(|other| *other == id))
+        {
+            Some(idx) => state.select(idx),
+            None => state.deselect(),
         }
+        self.fix_selection(state);
     }
 
-    /// Get access to the task which is being written by user while being added
-    pub fn get_editing_task(&self) -> String {
-        if let Status::Editing { edit, previous: _ } = &self.status {
-            edit.to_string()
+    /// Keep the selection in the current tab, the closest task in it is selected instead
+    pub fn fix_selection(&self, state: &mut State) {
+        let Some(selected) = state.cursor else {
+            return;
+        };
+        let visible = self.visible(state);
+        if visible.contains(&selected) {
+            return;
+        }
+        match visible
+            .iter()
+            .find(|idx| **idx >= selected)
+            .or(visible.last())
+        {
+            Some(idx) => state.select(*idx),
+            None => state.deselect(),
+        }
+    }
+
+    /// Select the task at a row of the current tab, the last one if it is past the end
+    pub fn select_row(&self, state: &mut State, row: usize) {
+        let visible = self.visible(state);
+        match visible.get(row).or(visible.last()) {
+            Some(idx) => state.select(*idx),
+            None => state.deselect(),
+        }
+    }
+
+    /// Move the selection by a number of rows in the current tab
+    ///
+    /// The first task is selected if there was no selection
+    pub fn move_selection_by(&self, state: &mut State, rows: isize) {
+        let row = state
+            .cursor
+            .and_then(|selected| self.visible(state).iter().position(|idx| *idx == selected))
+            .map_or(0, |row| row.saturating_add_signed(rows));
+        self.select_row(state, row);
+    }
+
+    /// Mark the tasks between the start of visual mode and the cursor
+    pub fn update_visual(&self, state: &mut State) {
+        let Some((anchor, before)) = &self.visual else {
+            return;
+        };
+        let visible = self.visible(state);
+        let row = |id: Option<&Id>| visible.iter().position(|idx| state.ids.get(*idx) == id);
+        let (Some(start), Some(end)) = (
+            row(Some(anchor)),
+            row(state.cursor.and_then(|idx| state.ids.get(idx))),
+        ) else {
+            return;
+        };
+        let mut marked = before.clone();
+        marked.extend(
+            visible[start.min(end)..=start.max(end)]
+                .iter()
+                .map(|idx| state.ids[*idx]),
+        );
+        state.marked = marked;
+    }
+
+    /// Change status of the app
+    pub fn switch_status(&mut self, new_status: Status) {
+        self.status = new_status;
+    }
+
+    /// The form of the task being added/changed
+    pub fn form_mut(&mut self) -> Option<&mut TaskForm> {
+        if let Status::Editing { edit, previous: _ } = &mut self.status {
+            Some(edit.as_mut())
         } else {
-            String::new()
+            None
         }
     }
 
@@ -1,14 +1,22 @@
+use std::io::IsTerminal;
+
 use clap::{Args as ClapArgs, Parser, Subcommand};
 use cli_table::{print_stdout, Cell, Color, Style, Table};
-use sublime_fuzzy::best_match;
 
 use crate::{
+    backup::{find_snapshot, list_snapshots},
+    config::{self, config, Filter, SortBy},
+    crypto::{self, KeySource},
     files::{
-        check_existing_metadata, create_metadata, enter_data_to_file, read_data_from_file,
-        remove_metadata,
+        archive_tasks, check_existing_metadata, create_metadata, enter_data_to_file, export_tasks,
+        import_tasks, read_archive, read_data_from_file, remove_metadata,
     },
     format_date,
-    state::State,
+    formats::{markdown, FormatKind},
+    history::{Operation, Step},
+    oplog, parse_age, search,
+    state::{State, Task},
+    sync::{sync, task_log},
     tui::run,
     Id, Result,
 };
@@ -18,6 +26,10 @@ use crate::{
 pub struct Args {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Theme of the TUI, a built-in one (dark, light, high-contrast, solarized),
+    /// one from the themes folder or the path of a theme file
+    #[arg(long)]
+    theme: Option<String>,
 }
 
 /// All the available commands
@@ -35,6 +47,162 @@ enum Commands {
     Edit(EditArgs),
     /// Mark a task complete or incomplete
     Mark(MarkArgs),
+    /// Revert the last change made to tasks
+    Undo,
+    /// Apply the last reverted change again
+    Redo,
+    /// Manage the deleted tasks
+    #[command(subcommand)]
+    Trash(TrashCommands),
+    /// Bring back a deleted task from the trash
+    Restore(RestoreArgs),
+    /// Move completed tasks out of the active list
+    Archive(ArchiveArgs),
+    /// Manage the automatic backups of tasks
+    #[command(subcommand)]
+    Backup(BackupCommands),
+    /// Add tasks from a file in another format
+    Import(ImportArgs),
+    /// Write all the tasks in another format
+    Export(ExportArgs),
+    /// Reconcile tasks with the checklist in a markdown notes file
+    SyncMd(SyncMdArgs),
+    /// Commit the tasks to git and exchange them with a remote
+    Sync(SyncArgs),
+    /// Show the history of a task recorded by sync
+    Log(LogArgs),
+    /// Keep tasks in per-device operation logs which can be shared
+    #[command(subcommand)]
+    Oplog(OplogCommands),
+    /// Encrypt the stored tasks with a passphrase or a keyfile
+    Encrypt(EncryptArgs),
+    /// Store the tasks in plain text again
+    Decrypt,
+    /// Show or change the settings in `config.toml`
+    #[command(subcommand)]
+    Config(ConfigCommands),
+}
+
+/// Actions available for the configuration
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the value in effect for a setting, like `general.date_format`
+    Get { key: String },
+    /// Change a setting in the configuration file
+    Set { key: String, value: String },
+    /// Open the configuration file in $VISUAL or $EDITOR
+    Edit,
+    /// Print the path of the configuration file
+    Path,
+}
+
+#[derive(ClapArgs)]
+struct EncryptArgs {
+    /// Use the contents of this file as the key instead of a passphrase
+    #[arg(long)]
+    keyfile: Option<std::path::PathBuf>,
+}
+
+/// Actions available for the operation logs
+#[derive(Subcommand)]
+enum OplogCommands {
+    /// Start keeping tasks in the operation logs
+    Enable,
+    /// Drop the operations of this device which were superseded
+    Compact,
+}
+
+#[derive(ClapArgs)]
+struct SyncArgs {
+    /// Url or path of the git repository to sync with, remembered for later syncs
+    #[arg(long)]
+    remote: Option<String>,
+}
+
+#[derive(ClapArgs)]
+struct LogArgs {
+    /// Id of the task
+    #[arg(short)]
+    id: Id,
+}
+
+#[derive(ClapArgs)]
+struct SyncMdArgs {
+    /// Markdown file containing `- [ ] item` checklists
+    file: std::path::PathBuf,
+}
+
+#[derive(ClapArgs)]
+struct ImportArgs {
+    /// Format of the file
+    #[arg(long)]
+    from: FormatKind,
+    /// File to read the tasks from
+    file: std::path::PathBuf,
+}
+
+#[derive(ClapArgs)]
+struct ExportArgs {
+    /// Format to write the tasks in
+    #[arg(long)]
+    to: FormatKind,
+    /// Write to a file instead of printing the tasks
+    #[arg(short)]
+    output: Option<std::path::PathBuf>,
+}
+
+/// Actions available for the backups
+#[derive(Subcommand)]
+enum BackupCommands {
+    /// List out all the available snapshots
+    List,
+    /// Replace the tasks with the ones from a snapshot
+    Restore(BackupRestoreArgs),
+}
+
+#[derive(ClapArgs)]
+struct BackupRestoreArgs {
+    /// Name of the snapshot as shown by `backup list`
+    snapshot: String,
+    /// Restore without asking for confirmation
+    #[arg(short)]
+    yes: bool,
+}
+
+/// Actions available for the trash
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// List out all the deleted tasks
+    List,
+    /// Permanently delete tasks from the trash
+    Purge(PurgeArgs),
+}
+
+#[derive(ClapArgs)]
+struct ArchiveArgs {
+    /// Archive a particular task, even if it is not completed
+    #[arg(short)]
+    id: Option<Id>,
+    /// Only archive the completed tasks matching a fuzzy search
+    #[arg(short = 'f')]
+    fuzzy: Option<String>,
+    /// Only archive the completed tasks last updated before this age, e.g. 30d
+    #[arg(long, value_parser = parse_age)]
+    older_than: Option<chrono::Duration>,
+}
+
+#[derive(ClapArgs)]
+struct RestoreArgs {
+    /// Id of the task to be restored
+    #[arg(short)]
+    id: Id,
+}
+
+#[derive(ClapArgs)]
+struct PurgeArgs {
+    /// Only delete tasks which were trashed before this age, e.g. 30d, 2w, 12h
+    #[arg(long, value_parser = parse_age, default_value = "0d")]
+    older_than: chrono::Duration,
 }
 
 #[derive(ClapArgs)]
@@ -48,9 +216,26 @@ struct ListArgs {
     /// Show a task with particular id
     #[arg(short)]
     id: Option<Id>,
-    /// Get the required tasks using a fuzzy search
+    /// Get the required tasks using a fuzzy search, best matches first
+    ///
+    /// The description, notes, project and tags of the tasks are searched
     #[arg(short = 'f')]
     fuzzy: Option<String>,
+    /// Show at most this many tasks
+    #[arg(long)]
+    limit: Option<usize>,
+    /// Show how well each task matches the fuzzy search
+    #[arg(long)]
+    score: bool,
+    /// List the archived tasks instead of the active ones
+    #[arg(short, long)]
+    archived: bool,
+    /// Order of the tasks, `general.sort` of the configuration by default
+    #[arg(long)]
+    sort: Option<SortBy>,
+    /// Which tasks to list, `general.filter` of the configuration by default
+    #[arg(long)]
+    filter: Option<Filter>,
 }
 
 #[derive(ClapArgs)]
@@ -81,23 +266,71 @@ struct MarkArgs {
     id: Id,
 }
 
-fn show_multiple_tasks_in_a_table(data: State, options: &ListArgs) -> Result<()> {
-    let mut table = Vec::new();
-    for task in data.get_tasks() {
+/// Wrap the chars at the given indices in bold and underline escapes
+fn highlight(text: &str, chars: &[usize]) -> String {
+    let mut highlighted = String::with_capacity(text.len());
+    for (idx, ch) in text.chars().enumerate() {
+        if chars.contains(&idx) {
+            highlighted.push_str("\x1b[1;4m");
+            highlighted.push(ch);
+            highlighted.push_str("\x1b[22;24m");
+        } else {
+            highlighted.push(ch);
+        }
+    }
+    highlighted
+}
+
+/// How well a task matches the fuzzy search, along with the matched chars of its description
+type Score = Option<(isize, Vec<usize>)>;
+
+/// The tasks to list, best matches of the fuzzy search first and at most `limit` of them
+fn listed_tasks<'a>(data: &'a State, options: &ListArgs) -> Vec<(&'a Task, Score)> {
+    let general = &config().general;
+    let mut tasks = data.get_tasks();
+    options.sort.unwrap_or(general.sort).sort(&mut tasks);
+    let filter = options.filter.unwrap_or(general.filter);
+    let mut found = Vec::new();
+    for task in tasks {
+        if !filter.matches(task) {
+            continue;
+        }
         if (options.completed.is_some() && !task.completed)
             || (options.incomplete.is_some() && task.completed)
         {
             continue;
         }
         // fuzzy search
-        if let Some(search) = &options.fuzzy {
-            if best_match(search, &task.desc).is_none() {
-                continue;
-            }
-        }
-        table.push(vec![
+        let score = match &options.fuzzy {
+            Some(query) => match search::score(query, task) {
+                Some(score) => Some(score),
+                None => continue,
+            },
+            None => None,
+        };
+        found.push((task, score));
+    }
+    // best matches first, tasks matching as well keep the order they are sorted in
+    found.sort_by_key(|(_, score)| std::cmp::Reverse(score.as_ref().map(|(score, _)| *score)));
+    found.truncate(options.limit.unwrap_or(usize::MAX));
+    found
+}
+
+fn show_multiple_tasks_in_a_table(data: State, options: &ListArgs) -> Result<()> {
+    let found = listed_tasks(&data, options);
+
+    // escapes would end up in files when the output is redirected
+    let highlighted = std::io::stdout().is_terminal();
+    let show_score = options.score && options.fuzzy.is_some();
+    let mut table = Vec::new();
+    for (task, score) in found {
+        let desc = match &score {
+            Some((_, chars)) if highlighted => highlight(&task.desc, chars),
+            _ => task.desc.clone(),
+        };
+        let mut row = vec![
             task.id.cell(),
-            task.desc.clone().cell(),
+            desc.cell(),
             match task.completed {
                 true => "Completed".cell().foreground_color(Some(Color::Green)),
                 false => "Pending"
@@ -106,9 +339,13 @@ fn show_multiple_tasks_in_a_table(data: State, options: &ListArgs) -> Result<()>
                     .foreground_color(Some(Color::Red)),
             },
             format_date(task.last_updated).cell(),
-        ]);
+        ];
+        if show_score {
+            row.push(score.map_or(0, |(score, _)| score).cell());
+        }
+        table.push(row);
     }
-    let table = table.table().title(vec![
+    let mut title = vec![
         "Task ID"
             .cell()
             .bold(true)
@@ -125,29 +362,182 @@ fn show_multiple_tasks_in_a_table(data: State, options: &ListArgs) -> Result<()>
             .cell()
             .bold(true)
             .foreground_color(Some(Color::Blue)),
+    ];
+    if show_score {
+        title.push(
+            "Score"
+                .cell()
+                .bold(true)
+                .foreground_color(Some(Color::Blue)),
+        );
+    }
+    let table = table.table().title(title);
+    print_stdout(table)?;
+    Ok(())
+}
+
+fn show_trash_in_a_table(data: &State) -> Result<()> {
+    let table = data
+        .trash
+        .iter()
+        .map(|trashed| {
+            vec![
+                trashed.task.id.cell(),
+                trashed.task.desc.clone().cell(),
+                format_date(trashed.deleted).cell(),
+            ]
+        })
+        .collect::<Vec<_>>()
+        .table()
+        .title(vec![
+            "Task ID"
+                .cell()
+                .bold(true)
+                .foreground_color(Some(Color::Blue)),
+            "Task Description"
+                .cell()
+                .bold(true)
+                .foreground_color(Some(Color::Blue)),
+            "Deleted"
+                .cell()
+                .bold(true)
+                .foreground_color(Some(Color::Blue)),
+        ]);
+    print_stdout(table)?;
+    Ok(())
+}
+
+fn show_snapshots_in_a_table() -> Result<()> {
+    let mut table = Vec::new();
+    for snapshot in list_snapshots()? {
+        table.push(vec![
+            snapshot.name.clone().cell(),
+            format_date(snapshot.taken).cell(),
+            snapshot.tasks()?.len().cell(),
+        ]);
+    }
+    if table.is_empty() {
+        println!("No backups yet");
+        return Ok(());
+    }
+    let table = table.table().title(vec![
+        "Snapshot"
+            .cell()
+            .bold(true)
+            .foreground_color(Some(Color::Blue)),
+        "Taken"
+            .cell()
+            .bold(true)
+            .foreground_color(Some(Color::Blue)),
+        "Tasks"
+            .cell()
+            .bold(true)
+            .foreground_color(Some(Color::Blue)),
     ]);
     print_stdout(table)?;
     Ok(())
 }
 
+/// Show the recorded versions of a task in a table
+fn show_task_log(id: Id) -> Result<()> {
+    let log = task_log(id)?;
+    if log.is_empty() {
+        println!("No history recorded for task {id}, run `sync` to record changes");
+        return Ok(());
+    }
+    let table = log
+        .iter()
+        .map(|entry| {
+            vec![
+                entry.commit.clone().cell(),
+                format_date(entry.date).cell(),
+                entry.change.clone().cell(),
+                entry
+                    .task
+                    .as_ref()
+                    .map(|task| task.desc.clone())
+                    .unwrap_or_default()
+                    .cell(),
+            ]
+        })
+        .table()
+        .title(vec![
+            "Commit"
+                .cell()
+                .bold(true)
+                .foreground_color(Some(Color::Blue)),
+            "Date".cell().bold(true).foreground_color(Some(Color::Blue)),
+            "Change"
+                .cell()
+                .bold(true)
+                .foreground_color(Some(Color::Blue)),
+            "Description"
+                .cell()
+                .bold(true)
+                .foreground_color(Some(Color::Blue)),
+        ]);
+    print_stdout(table)?;
+    Ok(())
+}
+
+/// Print what applying the given step would change
+fn show_step_summary(step: &Step) {
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for op in step {
+        match op {
+            Operation::Insert { .. } | Operation::Restore { .. } => added += 1,
+            Operation::Remove { .. } | Operation::Trash { .. } => removed += 1,
+            Operation::Update { .. } => changed += 1,
+        }
+        println!("  {}", op.describe());
+    }
+    println!("{added} added, {removed} removed, {changed} changed");
+}
+
 impl Args {
     /// The main function which runs the entire app
     pub fn run(&self) -> Result<()> {
+        // a broken configuration can still be fixed with the config command
+        if !matches!(self.command, Some(Commands::Config(_))) {
+            config::load()?;
+        }
         if let Some(command) = &self.command {
+            if !matches!(command, Commands::Encrypt(_) | Commands::Config(_)) {
+                crypto::unlock()?;
+            }
             match command {
                 Commands::Clean => {
-                    println!("Are you sure you want to delete all tasks?(y/n)");
-                    let mut ans = String::new();
-                    std::io::stdin().read_line(&mut ans)?;
+                    let mut ans = String::from("y");
+                    if config().general.confirm {
+                        println!("Are you sure you want to delete all tasks?(y/n/t)");
+                        println!("A backup is kept in any case, see `backup list`");
+                        println!("t moves them to the trash, from where they can be restored");
+                        ans.clear();
+                        std::io::stdin().read_line(&mut ans)?;
+                    }
                     let ans = ans.trim();
                     if ans.eq("y") {
                         remove_metadata()?;
                         println!("All tasks removed successfuly");
+                    } else if ans.eq("t") && check_existing_metadata() {
+                        let mut data = read_data_from_file()?;
+                        let count = data.trash_all();
+                        enter_data_to_file(&mut data)?;
+                        println!("Moved {count} tasks to the trash");
                     }
                 }
                 Commands::List(options) => {
                     if check_existing_metadata() {
-                        let data = read_data_from_file()?;
+                        let data = if options.archived {
+                            State::from_tasks(
+                                read_archive()?
+                                    .into_iter()
+                                    .map(|archived| archived.task)
+                                    .collect(),
+                            )
+                        } else {
+                            read_data_from_file()?
+                        };
                         if data.tasks.is_empty() {
                             println!("No tasks yet!");
                         } else {
@@ -180,7 +570,7 @@ impl Args {
                     }
                     let mut data = read_data_from_file()?;
                     data.add_task(&add_args.description);
-                    enter_data_to_file(&data)?;
+                    enter_data_to_file(&mut data)?;
                     println!("Added new task successfully");
                 }
                 Commands::Remove(remove_args) => {
@@ -189,16 +579,18 @@ impl Args {
                         if data.remove_task(&remove_args.id).is_none() {
                             println!("No such task found");
                         } else {
-                            enter_data_to_file(&data)?;
+                            enter_data_to_file(&mut data)?;
                         }
                     }
                 }
                 Commands::Edit(edit_args) => {
                     if check_existing_metadata() {
                         let mut data = read_data_from_file()?;
-                        if data.remove_task(&edit_args.id).is_some() {
-                            data.add_task(&edit_args.description);
-                            enter_data_to_file(&data)?;
+                        if data
+                            .edit_task(edit_args.id, &edit_args.description)
+                            .is_some()
+                        {
+                            enter_data_to_file(&mut data)?;
                             println!("Task changed successfully");
                         } else {
                             println!("No task with this id found");
@@ -209,7 +601,7 @@ impl Args {
                     if check_existing_metadata() {
                         let mut data = read_data_from_file()?;
                         if let Some(complete) = data.toggle_task_status_by_id(mark_args.id) {
-                            enter_data_to_file(&data)?;
+                            enter_data_to_file(&mut data)?;
                             if complete {
                                 println!("Marked task as complete");
                             } else {
@@ -220,10 +612,320 @@ impl Args {
                         }
                     }
                 }
+                Commands::Undo => {
+                    if check_existing_metadata() {
+                        let mut data = read_data_from_file()?;
+                        if let Some(step) = data.undo() {
+                            enter_data_to_file(&mut data)?;
+                            for op in step.iter().rev() {
+                                println!("Undid: {}", op.describe());
+                            }
+                        } else {
+                            println!("Nothing to undo");
+                        }
+                    }
+                }
+                Commands::Redo => {
+                    if check_existing_metadata() {
+                        let mut data = read_data_from_file()?;
+                        if let Some(step) = data.redo() {
+                            enter_data_to_file(&mut data)?;
+                            for op in &step {
+                                println!("Redid: {}", op.describe());
+                            }
+                        } else {
+                            println!("Nothing to redo");
+                        }
+                    }
+                }
+                Commands::Trash(TrashCommands::List) => {
+                    if check_existing_metadata() {
+                        let data = read_data_from_file()?;
+                        if data.trash.is_empty() {
+                            println!("Trash is empty");
+                        } else {
+                            show_trash_in_a_table(&data)?;
+                        }
+                    } else {
+                        println!("Trash is empty");
+                    }
+                }
+                Commands::Trash(TrashCommands::Purge(purge_args)) => {
+                    if check_existing_metadata() {
+                        let mut data = read_data_from_file()?;
+                        let count = data.purge_trash(purge_args.older_than);
+                        enter_data_to_file(&mut data)?;
+                        println!("Permanently deleted {count} tasks");
+                    }
+                }
+                Commands::Restore(restore_args) => {
+                    if check_existing_metadata() {
+                        let mut data = read_data_from_file()?;
+                        if data.restore_task(&restore_args.id).is_some() {
+                            enter_data_to_file(&mut data)?;
+                            println!("Task restored successfully");
+                        } else {
+                            println!("No such task found in trash");
+                        }
+                    }
+                }
+                Commands::Archive(archive_args) => {
+                    if check_existing_metadata() {
+                        let mut data = read_data_from_file()?;
+                        let ids: Vec<Id> = if let Some(id) = archive_args.id {
+                            vec![id]
+                        } else {
+                            let cutoff = archive_args
+                                .older_than
+                                .map(|age| chrono::Local::now() - age);
+                            data.get_tasks()
+                                .into_iter()
+                                .filter(|task| task.completed)
+                                .filter(|task| {
+                                    cutoff.is_none_or(|cutoff| task.last_updated < cutoff)
+                                })
+                                .filter(|task| {
+                                    archive_args
+                                        .fuzzy
+                                        .as_ref()
+                                        .is_none_or(|query| search::score(query, task).is_some())
+                                })
+                                .map(|task| task.id)
+                                .collect()
+                        };
+                        let tasks = data.detach_tasks(&ids);
+                        if tasks.is_empty() {
+                            println!("No tasks to archive");
+                        } else {
+                            let count = archive_tasks(tasks)?;
+                            enter_data_to_file(&mut data)?;
+                            println!("Archived {count} tasks");
+                        }
+                    }
+                }
+                Commands::Backup(BackupCommands::List) => {
+                    show_snapshots_in_a_table()?;
+                }
+                Commands::Backup(BackupCommands::Restore(restore_args)) => {
+                    let Some(snapshot) = find_snapshot(&restore_args.snapshot)? else {
+                        println!("No such snapshot found");
+                        return Ok(());
+                    };
+                    let mut data = read_data_from_file()?;
+                    let step = data.changes_to(&snapshot.tasks()?);
+                    if step.is_empty() {
+                        println!("Tasks are already the same as in this snapshot");
+                        return Ok(());
+                    }
+                    println!("Restoring {} will:", snapshot.name);
+                    show_step_summary(&step);
+                    if !restore_args.yes && config().general.confirm {
+                        println!("Are you sure you want to restore this snapshot?(y/n)");
+                        let mut ans = String::new();
+                        std::io::stdin().read_line(&mut ans)?;
+                        if ans.trim() != "y" {
+                            return Ok(());
+                        }
+                    }
+                    data.apply_changes(step);
+                    enter_data_to_file(&mut data)?;
+                    println!("Snapshot restored successfully, use undo to revert it");
+                }
+                Commands::Import(import_args) => {
+                    if !check_existing_metadata() {
+                        create_metadata()?;
+                    }
+                    let input = std::fs::read_to_string(&import_args.file)?;
+                    let mut data = read_data_from_file()?;
+                    let summary = import_tasks(&mut data, &*import_args.from.format(), &input)?;
+                    enter_data_to_file(&mut data)?;
+                    println!(
+                        "Imported tasks: {} added, {} updated, {} unchanged",
+                        summary.added, summary.updated, summary.unchanged
+                    );
+                }
+                Commands::Export(export_args) => {
+                    let data = if check_existing_metadata() {
+                        read_data_from_file()?
+                    } else {
+                        State::new()
+                    };
+                    let output = export_tasks(&data, &*export_args.to.format())?;
+                    match &export_args.output {
+                        Some(path) => std::fs::write(path, output)?,
+                        None => print!("{output}"),
+                    }
+                }
+                Commands::SyncMd(sync_args) => {
+                    if !check_existing_metadata() {
+                        create_metadata()?;
+                    }
+                    let input = std::fs::read_to_string(&sync_args.file)?;
+                    let modified = std::fs::metadata(&sync_args.file)?.modified()?;
+                    let mut data = read_data_from_file()?;
+                    let (output, step, summary) = markdown::sync(&data, &input, modified.into());
+                    data.apply_changes(step);
+                    enter_data_to_file(&mut data)?;
+                    if output != input {
+                        std::fs::write(&sync_args.file, output)?;
+                    }
+                    println!(
+                        "Synced: {} new tasks, {} updated from file, {} updated in file",
+                        summary.added, summary.from_file, summary.to_file
+                    );
+                }
+                Commands::Sync(sync_args) => {
+                    if !check_existing_metadata() {
+                        create_metadata()?;
+                    }
+                    let report = sync(sync_args.remote.as_deref())?;
+                    if report.local_only {
+                        println!("No remote configured, pass --remote to sync with one");
+                    }
+                    let mut done = Vec::new();
+                    if report.committed {
+                        done.push("committed local changes");
+                    }
+                    if report.merged {
+                        done.push("merged remote changes");
+                    } else if report.pulled {
+                        done.push("pulled remote changes");
+                    }
+                    if report.pushed {
+                        done.push("pushed");
+                    }
+                    if done.is_empty() {
+                        println!("Already up to date");
+                    } else {
+                        println!("Synced: {}", done.join(", "));
+                    }
+                }
+                Commands::Log(log_args) => {
+                    if check_existing_metadata() {
+                        show_task_log(log_args.id)?;
+                    }
+                }
+                Commands::Oplog(OplogCommands::Enable) => {
+                    if !check_existing_metadata() {
+                        create_metadata()?;
+                    }
+                    if oplog::enabled() {
+                        println!("Operation logs are already enabled");
+                    } else {
+                        let data = read_data_from_file()?;
+                        let tasks: Vec<_> = data.get_tasks().into_iter().cloned().collect();
+                        let added = oplog::enable(&tasks)?;
+                        read_data_from_file()?;
+                        println!("Operation logs enabled, {added} tasks added to the log");
+                    }
+                }
+                Commands::Oplog(OplogCommands::Compact) => {
+                    if check_existing_metadata() && oplog::enabled() {
+                        let (before, after) = oplog::compact()?;
+                        println!("Compacted the log from {before} to {after} operations");
+                    } else {
+                        println!("Operation logs are not enabled");
+                    }
+                }
+                Commands::Encrypt(encrypt_args) => {
+                    if !check_existing_metadata() {
+                        create_metadata()?;
+                    }
+                    let source = match &encrypt_args.keyfile {
+                        Some(path) => KeySource::Keyfile(path.clone()),
+                        None => KeySource::Passphrase,
+                    };
+                    let count = crypto::encrypt_store(source)?;
+                    println!("Encrypted {count} files");
+                    if let Some(path) = &encrypt_args.keyfile {
+                        println!("Set TODO_CLI_KEYFILE={} to unlock them", path.display());
+                    }
+                }
+                Commands::Decrypt => {
+                    if check_existing_metadata() {
+                        let count = crypto::decrypt_store()?;
+                        println!("Decrypted {count} files");
+                    }
+                }
+                Commands::Config(ConfigCommands::Get { key }) => {
+                    config::load()?;
+                    println!("{}", config::get(key)?);
+                }
+                Commands::Config(ConfigCommands::Set { key, value }) => {
+                    let path = config::set(key, value)?;
+                    println!("Updated {}", path.display());
+                }
+                Commands::Config(ConfigCommands::Edit) => {
+                    config::edit()?;
+                }
+                Commands::Config(ConfigCommands::Path) => {
+                    println!("{}", config::config_path().display());
+                }
             }
         } else {
-            run()?;
+            run(self.theme.as_deref())?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local};
+
+    use super::*;
+
+    #[test]
+    fn check_listed_tasks() {
+        let task = |id, desc: &str, priority, due: Option<i64>| Task {
+            id,
+            desc: desc.to_string(),
+            priority,
+            due: due.map(|days| Local::now() + Duration::days(days)),
+            ..Default::default()
+        };
+        let data = State::from_tasks(vec![
+            task(1, "reports archive", Some('B'), None),
+            task(2, "groceries", Some('A'), Some(1)),
+            task(3, "repair the port", Some('C'), Some(2)),
+            task(4, "report", None, Some(7)),
+            task(5, "read report", Some('A'), Some(3)),
+        ]);
+        let mut options = ListArgs {
+            completed: None,
+            incomplete: None,
+            id: None,
+            fuzzy: Some("report".to_string()),
+            limit: None,
+            score: true,
+            archived: false,
+            sort: Some(SortBy::Priority),
+            filter: Some(Filter::All),
+        };
+        let found = listed_tasks(&data, &options);
+        let ids: Vec<Id> = found.iter().map(|(task, _)| task.id).collect();
+        let scores: Vec<isize> = found
+            .iter()
+            .map(|(_, score)| score.as_ref().unwrap().0)
+            .collect();
+        // tasks matching as well keep the priority order, the weak match goes last
+        assert_eq!(ids, [5, 1, 4, 3]);
+        assert!(scores[2] > scores[3]);
+
+        options.limit = Some(2);
+        let limited = listed_tasks(&data, &options);
+        assert_eq!(limited.len(), 2);
+        assert!(limited
+            .iter()
+            .zip(&found)
+            .all(|((task, _), (other, _))| task.id == other.id));
+
+        // without a search the tasks keep the order they are sorted in
+        options.fuzzy = None;
+        let ids: Vec<Id> = listed_tasks(&data, &options)
+            .iter()
+            .map(|(task, _)| task.id)
+            .collect();
+        assert_eq!(ids, [2, 5]);
+    }
+}
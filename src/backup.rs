@@ -0,0 +1,187 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+use crate::{
+    config::config,
+    crypto,
+    files::{data_file, no_metadata, read_tasks_from, CSV_NAME},
+    state::Task,
+    Result,
+};
+
+/// Directory inside the storage folder holding the snapshots
+pub const BACKUP_FOLDER: &str = "backups";
+
+/// Format of the timestamp used to name a snapshot
+const SNAPSHOT_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+
+/// Environment variable with the number of most recent snapshots to keep,
+/// it takes precedence over `storage.backup_keep` of the configuration
+const KEEP_LAST_VAR: &str = "TODO_CLI_BACKUP_KEEP";
+/// Environment variable with the number of days for which a daily snapshot is
+/// kept, it takes precedence over `storage.backup_days` of the configuration
+const KEEP_DAYS_VAR: &str = "TODO_CLI_BACKUP_DAYS";
+
+/// A copy of the data file taken at some point of time
+pub struct Snapshot {
+    /// name used to refer to the snapshot from cli
+    pub name: String,
+    pub taken: DateTime<Local>,
+    pub path: PathBuf,
+}
+
+impl Snapshot {
+    /// Read the tasks stored in this snapshot
+    pub fn tasks(&self) -> Result<Vec<Task>> {
+        read_tasks_from(&self.path)
+    }
+}
+
+fn backup_dir() -> Result<PathBuf> {
+    data_file(BACKUP_FOLDER).ok_or_else(no_metadata)
+}
+
+/// Copy the current data file into the backups, if it changed since the last snapshot
+///
+/// Old snapshots are pruned afterwards according to the retention policy
+pub fn snapshot() -> Result<()> {
+    let data = data_file(CSV_NAME).ok_or_else(no_metadata)?;
+    if !data.exists() {
+        return Ok(());
+    }
+    let contents = crypto::read(&data)?;
+    let snapshots = list_snapshots()?;
+    if let Some(latest) = snapshots.first() {
+        if crypto::read(&latest.path)? == contents {
+            return Ok(());
+        }
+    }
+
+    let dir = backup_dir()?;
+    fs::create_dir_all(&dir)?;
+    let name = Local::now().format(SNAPSHOT_FORMAT).to_string();
+    crypto::write(&dir.join(format!("{name}.csv")), &contents)?;
+    prune(retention_keep_last(), Duration::days(retention_keep_days()))?;
+    Ok(())
+}
+
+/// All the available snapshots, newest first
+pub fn list_snapshots() -> Result<Vec<Snapshot>> {
+    let dir = backup_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        if let Some(snapshot) = parse_snapshot(&entry?.path()) {
+            snapshots.push(snapshot);
+        }
+    }
+    snapshots.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(snapshots)
+}
+
+/// Find a snapshot by its name
+pub fn find_snapshot(name: &str) -> Result<Option<Snapshot>> {
+    let name = name.trim_end_matches(".csv");
+    Ok(list_snapshots()?
+        .into_iter()
+        .find(|snapshot| snapshot.name == name))
+}
+
+fn parse_snapshot(path: &Path) -> Option<Snapshot> {
+    if path.extension()? != "csv" {
+        return None;
+    }
+    let name = path.file_stem()?.to_str()?.to_string();
+    let taken = NaiveDateTime::parse_from_str(&name, SNAPSHOT_FORMAT).ok()?;
+    Some(Snapshot {
+        taken: Local.from_local_datetime(&taken).earliest()?,
+        path: path.to_path_buf(),
+        name,
+    })
+}
+
+fn retention_keep_last() -> usize {
+    std::env::var(KEEP_LAST_VAR)
+        .ok()
+        .and_then(|keep| keep.parse().ok())
+        .unwrap_or(config().storage.backup_keep)
+}
+
+fn retention_keep_days() -> i64 {
+    std::env::var(KEEP_DAYS_VAR)
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(config().storage.backup_days)
+}
+
+/// Delete the snapshots which are not needed any more
+fn prune(keep_last: usize, daily: Duration) -> Result<()> {
+    let snapshots = list_snapshots()?;
+    for snapshot in unneeded(&snapshots, keep_last, Local::now() - daily) {
+        fs::remove_file(&snapshot.path)?;
+    }
+    Ok(())
+}
+
+/// The snapshots, newest first, which the retention policy lets go
+///
+/// The `keep_last` newest snapshots are always kept, apart from them only
+/// the newest snapshot of each day after `cutoff` is retained
+fn unneeded(snapshots: &[Snapshot], keep_last: usize, cutoff: DateTime<Local>) -> Vec<&Snapshot> {
+    let mut days: HashSet<NaiveDate> = HashSet::new();
+    snapshots
+        .iter()
+        .enumerate()
+        .filter(|(idx, snapshot)| {
+            let first_of_day = days.insert(snapshot.taken.date_naive());
+            !(*idx < keep_last || (first_of_day && snapshot.taken > cutoff))
+        })
+        .map(|(_, snapshot)| snapshot)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_retention() {
+        let names = [
+            "20261019-180000-000",
+            "20261019-120000-000",
+            "20261019-090000-000",
+            "20261018-200000-000",
+            "20261018-080000-000",
+            "20261017-100000-000",
+            "20261010-100000-000",
+        ];
+        let snapshots: Vec<Snapshot> = names
+            .iter()
+            .filter_map(|name| parse_snapshot(Path::new(&format!("{name}.csv"))))
+            .collect();
+        assert_eq!(snapshots.len(), names.len());
+        let cutoff = parse_snapshot(Path::new("20261016-000000-000.csv"))
+            .unwrap()
+            .taken;
+        let gone: Vec<&str> = unneeded(&snapshots, 2, cutoff)
+            .into_iter()
+            .map(|snapshot| snapshot.name.as_str())
+            .collect();
+        // the two newest, then the newest of the 18th and of the 17th
+        assert_eq!(
+            gone,
+            [
+                "20261019-090000-000",
+                "20261018-080000-000",
+                "20261010-100000-000"
+            ]
+        );
+    }
+}
@@ -0,0 +1,798 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    Local,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    keymap::{parse_binding, Action, KEYMAP_NAME},
+    parse_age, search,
+    state::Task,
+    theme::{parse_color, Theme, ThemeSpec},
+    Result,
+};
+
+/// Name of the configuration file
+pub(crate) const CONFIG_NAME: &str = "config.toml";
+/// Folder of the configuration file inside the XDG config directory
+const CONFIG_FOLDER: &str = "todo-cli";
+/// Folder of the configuration file and the tasks inside the home directory
+const HOME_FOLDER: &str = ".todo-cli";
+
+/// Configuration loaded once at startup
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Everything which can be set in `config.toml`
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub general: General,
+    pub storage: Storage,
+    pub theme: ThemeSpec,
+    pub keys: Keys,
+    /// tabs of the TUI shown after the built-in ones
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub views: Vec<View>,
+}
+
+/// How tasks are shown and changed
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct General {
+    /// strftime format of dates in the current year
+    pub date_format: String,
+    /// strftime format of dates in other years
+    pub date_format_other_year: String,
+    /// order in which `list` shows tasks
+    pub sort: SortBy,
+    /// tasks which `list` shows
+    pub filter: Filter,
+    /// ask before destructive actions and before leaving the TUI
+    pub confirm: bool,
+    /// use the mouse in the TUI, which takes over selecting text in the terminal
+    pub mouse: bool,
+}
+
+impl Default for General {
+    fn default() -> Self {
+        Self {
+            date_format: "%d/%m, %H:%M".to_string(),
+            date_format_other_year: "%d/%m/%Y".to_string(),
+            sort: SortBy::default(),
+            filter: Filter::default(),
+            confirm: true,
+            mouse: true,
+        }
+    }
+}
+
+/// Orders in which tasks can be listed
+#[derive(Serialize, Deserialize, clap::ValueEnum, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortBy {
+    /// the order in which tasks are stored, newest first
+    #[default]
+    Manual,
+    Created,
+    Updated,
+    Due,
+    Priority,
+    Description,
+}
+
+impl SortBy {
+    /// Sort tasks in this order, tasks without a due date or priority go last
+    pub fn sort(self, tasks: &mut [&Task]) {
+        match self {
+            Self::Manual => {}
+            Self::Created => tasks.sort_by_key(|task| std::cmp::Reverse(task.created)),
+            Self::Updated => tasks.sort_by_key(|task| std::cmp::Reverse(task.last_updated)),
+            Self::Due => tasks.sort_by_key(|task| (task.due.is_none(), task.due)),
+            Self::Priority => tasks.sort_by_key(|task| (task.priority.is_none(), task.priority)),
+            Self::Description => tasks.sort_by_key(|task| task.desc.to_lowercase()),
+        }
+    }
+}
+
+/// Tasks which can be listed
+#[derive(Serialize, Deserialize, clap::ValueEnum, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Filter {
+    #[default]
+    All,
+    Pending,
+    Completed,
+}
+
+impl Filter {
+    pub fn matches(self, task: &Task) -> bool {
+        match self {
+            Self::All => true,
+            Self::Pending => !task.completed,
+            Self::Completed => task.completed,
+        }
+    }
+}
+
+/// A saved view, shown as a tab of the TUI with the tasks matching all of its settings
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct View {
+    pub name: String,
+    pub filter: Filter,
+    /// fuzzy search like `list -f`
+    pub search: Option<String>,
+    /// tasks having all of these tags
+    pub tags: Vec<String>,
+    pub project: Option<String>,
+    /// tasks due within this age from now like `7d`, overdue ones included
+    pub due_within: Option<String>,
+}
+
+impl View {
+    pub fn matches(&self, task: &Task) -> bool {
+        self.filter.matches(task)
+            && self
+                .search
+                .as_ref()
+                .is_none_or(|query| search::score(query, task).is_some())
+            && self.tags.iter().all(|tag| task.tags.contains(tag))
+            && self
+                .project
+                .as_ref()
+                .is_none_or(|project| task.project.as_ref() == Some(project))
+            && self
+                .due_within
+                .as_ref()
+                .and_then(|age| parse_age(age).ok())
+                .is_none_or(|age| task.due.is_some_and(|due| due <= Local::now() + age))
+    }
+}
+
+/// Where and how tasks are stored
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Storage {
+    /// folder holding the tasks, `~/.todo-cli` by default
+    pub path: Option<String>,
+    /// storage backend, detected from the folder if not set
+    pub backend: Option<Backend>,
+    /// age after which completed tasks are archived, like `30d`
+    pub archive_after: Option<String>,
+    /// number of most recent backups to keep
+    pub backup_keep: usize,
+    /// number of days for which a daily backup is kept
+    pub backup_days: i64,
+    /// keyfile used to unlock encrypted tasks
+    pub keyfile: Option<String>,
+    /// when the TUI saves by itself, `change` or the time without changes like `10s`
+    pub autosave: Option<String>,
+}
+
+impl Storage {
+    /// When the TUI saves by itself, off if `autosave` is not valid
+    pub fn autosave(&self) -> Autosave {
+        self.autosave
+            .as_deref()
+            .and_then(|autosave| parse_autosave(autosave).ok())
+            .unwrap_or(Autosave::Off)
+    }
+}
+
+/// When the TUI saves the tasks without being asked to
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Autosave {
+    /// only when leaving it
+    Off,
+    /// after every change
+    Change,
+    /// once no change was made for some time
+    Pause(std::time::Duration),
+}
+
+/// Parse `change` or the time without changes after which the TUI saves, like `10s`
+fn parse_autosave(autosave: &str) -> std::result::Result<Autosave, String> {
+    if autosave == "change" {
+        return Ok(Autosave::Change);
+    }
+    parse_age(autosave)
+        .and_then(|age| {
+            age.to_std()
+                .map_err(|_| format!("invalid age `{autosave}`"))
+        })
+        .map(Autosave::Pause)
+        .map_err(|message| format!("{message}, or `change` to save after every change"))
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self {
+            path: None,
+            backend: None,
+            archive_after: None,
+            backup_keep: 20,
+            backup_days: 30,
+            keyfile: None,
+            autosave: None,
+        }
+    }
+}
+
+/// Ways of storing tasks
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// a single csv file
+    Csv,
+    /// per-device operation logs
+    Oplog,
+}
+
+/// Keys of the actions in the task list, `keymap.toml` can replace them
+///
+/// Keys are characters like `q`, names like `enter`, `esc`, `up` and
+/// `down`, optionally with modifiers like `ctrl-r`. Chords of several keys
+/// are written like `dd` or `g g`
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keys {
+    pub quit: Vec<String>,
+    pub new: Vec<String>,
+    pub edit: Vec<String>,
+    pub delete: Vec<String>,
+    pub toggle: Vec<String>,
+    pub up: Vec<String>,
+    pub down: Vec<String>,
+    pub undo: Vec<String>,
+    pub redo: Vec<String>,
+    pub trash: Vec<String>,
+    /// switch to the next theme
+    pub theme: Vec<String>,
+    /// move the selection by the height of the list
+    pub page_up: Vec<String>,
+    pub page_down: Vec<String>,
+    /// select the first or the last task
+    pub top: Vec<String>,
+    pub bottom: Vec<String>,
+    /// show or hide the details of the selected task
+    pub details: Vec<String>,
+    /// mark the task under the cursor, or every task the cursor moves over in visual mode
+    pub mark: Vec<String>,
+    pub visual: Vec<String>,
+    pub unmark: Vec<String>,
+    /// set the tags, the project or the priority of the marked tasks
+    pub tag: Vec<String>,
+    pub project: Vec<String>,
+    pub priority: Vec<String>,
+    /// show the next or the previous tab, number keys also pick a tab
+    pub next_tab: Vec<String>,
+    pub prev_tab: Vec<String>,
+    /// start a search, then jump to the next or the previous match
+    pub search: Vec<String>,
+    pub next_match: Vec<String>,
+    pub prev_match: Vec<String>,
+    /// bring back the selected task of the trash, or delete it forever
+    pub restore: Vec<String>,
+    pub purge: Vec<String>,
+}
+
+impl Default for Keys {
+    fn default() -> Self {
+        let keys = |keys: &[&str]| keys.iter().map(|key| key.to_string()).collect();
+        Self {
+            quit: keys(&["q"]),
+            new: keys(&["i"]),
+            edit: keys(&["e"]),
+            delete: keys(&["x", "dd"]),
+            toggle: keys(&["enter"]),
+            up: keys(&["up", "k"]),
+            down: keys(&["down", "j"]),
+            undo: keys(&["u"]),
+            redo: keys(&["ctrl-r"]),
+            trash: keys(&["t"]),
+            theme: keys(&["T"]),
+            page_up: keys(&["pageup", "ctrl-b"]),
+            page_down: keys(&["pagedown", "ctrl-f"]),
+            top: keys(&["home", "gg"]),
+            bottom: keys(&["end", "G"]),
+            details: keys(&["p"]),
+            mark: keys(&["space"]),
+            visual: keys(&["v"]),
+            unmark: keys(&["esc"]),
+            tag: keys(&["#"]),
+            project: keys(&["m"]),
+            priority: keys(&["P"]),
+            next_tab: keys(&["tab"]),
+            prev_tab: keys(&["backtab"]),
+            search: keys(&["/"]),
+            next_match: keys(&["n"]),
+            prev_match: keys(&["N"]),
+            restore: keys(&["r"]),
+            purge: keys(&["D"]),
+        }
+    }
+}
+
+impl Keys {
+    /// Bindings of an action
+    pub fn bindings(&self, action: Action) -> &Vec<String> {
+        match action {
+            Action::Quit => &self.quit,
+            Action::NewTask => &self.new,
+            Action::Edit => &self.edit,
+            Action::Delete => &self.delete,
+            Action::Toggle => &self.toggle,
+            Action::MoveUp => &self.up,
+            Action::MoveDown => &self.down,
+            Action::Undo => &self.undo,
+            Action::Redo => &self.redo,
+            Action::Trash => &self.trash,
+            Action::NextTheme => &self.theme,
+            Action::PageUp => &self.page_up,
+            Action::PageDown => &self.page_down,
+            Action::Top => &self.top,
+            Action::Bottom => &self.bottom,
+            Action::Details => &self.details,
+            Action::Mark => &self.mark,
+            Action::Visual => &self.visual,
+            Action::Unmark => &self.unmark,
+            Action::Tag => &self.tag,
+            Action::Project => &self.project,
+            Action::Priority => &self.priority,
+            Action::NextTab => &self.next_tab,
+            Action::PrevTab => &self.prev_tab,
+            Action::Search => &self.search,
+            Action::NextMatch => &self.next_match,
+            Action::PrevMatch => &self.prev_match,
+            Action::Restore => &self.restore,
+            Action::Purge => &self.purge,
+        }
+    }
+
+    pub fn bindings_mut(&mut self, action: Action) -> &mut Vec<String> {
+        match action {
+            Action::Quit => &mut self.quit,
+            Action::NewTask => &mut self.new,
+            Action::Edit => &mut self.edit,
+            Action::Delete => &mut self.delete,
+            Action::Toggle => &mut self.toggle,
+            Action::MoveUp => &mut self.up,
+            Action::MoveDown => &mut self.down,
+            Action::Undo => &mut self.undo,
+            Action::Redo => &mut self.redo,
+            Action::Trash => &mut self.trash,
+            Action::NextTheme => &mut self.theme,
+            Action::PageUp => &mut self.page_up,
+            Action::PageDown => &mut self.page_down,
+            Action::Top => &mut self.top,
+            Action::Bottom => &mut self.bottom,
+            Action::Details => &mut self.details,
+            Action::Mark => &mut self.mark,
+            Action::Visual => &mut self.visual,
+            Action::Unmark => &mut self.unmark,
+            Action::Tag => &mut self.tag,
+            Action::Project => &mut self.project,
+            Action::Priority => &mut self.priority,
+            Action::NextTab => &mut self.next_tab,
+            Action::PrevTab => &mut self.prev_tab,
+            Action::Search => &mut self.search,
+            Action::NextMatch => &mut self.next_match,
+            Action::PrevMatch => &mut self.prev_match,
+            Action::Restore => &mut self.restore,
+            Action::Purge => &mut self.purge,
+        }
+    }
+}
+
+/// A problem in the configuration file, pointing to the line which caused it
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: {}", self.path.display(), self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+// errors returned from main are shown with `Debug`
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+fn check_date_format(format: &str) -> std::result::Result<(), String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(format!("invalid date format `{format}`"));
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Parse and validate the contents of a configuration file
+    pub fn parse(source: &str, path: &Path) -> std::result::Result<Self, ConfigError> {
+        let error = |line, message: String| ConfigError {
+            path: path.to_path_buf(),
+            line,
+            message,
+        };
+        let config: Config = toml::from_str(source).map_err(|err| {
+            let line = err.span().map(|span| line_of(source, span.start));
+            error(line, err.message().to_string())
+        })?;
+
+        let document = toml_edit::ImDocument::parse(source)
+            .map_err(|err| error(None, err.message().to_string()))?;
+        // line of a value for errors found after parsing
+        let line = |section: &str, key: &str| {
+            document
+                .get(section)
+                .and_then(|table| table.get(key))
+                .and_then(|value| value.span())
+                .map(|span| line_of(source, span.start))
+        };
+        let general = &config.general;
+        for (key, format) in [
+            ("date_format", &general.date_format),
+            ("date_format_other_year", &general.date_format_other_year),
+        ] {
+            check_date_format(format).map_err(|message| error(line("general", key), message))?;
+        }
+        if let Some(age) = &config.storage.archive_after {
+            parse_age(age).map_err(|message| error(line("storage", "archive_after"), message))?;
+        }
+        if let Some(autosave) = &config.storage.autosave {
+            parse_autosave(autosave)
+                .map_err(|message| error(line("storage", "autosave"), message))?;
+        }
+        for (key, color) in config.theme.colors() {
+            if let Some(color) = color {
+                parse_color(color).map_err(|message| error(line("theme", key), message))?;
+            }
+        }
+        if let Some(name) = &config.theme.name {
+            Theme::load(name).map_err(|err| error(line("theme", "name"), err.to_string()))?;
+        }
+        for (idx, view) in config.views.iter().enumerate() {
+            // line of a value of the view, or of the view itself
+            let line = |key: &str| {
+                let view = document
+                    .get("views")
+                    .and_then(|views| views.as_array_of_tables())
+                    .and_then(|views| views.get(idx))?;
+                view.get(key)
+                    .and_then(|value| value.span())
+                    .or_else(|| view.span())
+                    .map(|span| line_of(source, span.start))
+            };
+            if view.name.trim().is_empty() {
+                return Err(error(line("name"), "every view needs a name".to_string()));
+            }
+            if let Some(age) = &view.due_within {
+                parse_age(age).map_err(|message| error(line("due_within"), message))?;
+            }
+        }
+        for action in Action::ALL {
+            for binding in config.keys.bindings(action) {
+                parse_binding(binding)
+                    .map_err(|message| error(line("keys", action.name()), message))?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Replace the bindings of the actions listed in a keymap file
+    ///
+    /// The file has an array of keys for each action, like `delete = ["x", "dd"]`
+    pub fn apply_keymap(
+        &mut self,
+        source: &str,
+        path: &Path,
+    ) -> std::result::Result<(), ConfigError> {
+        let error = |line, message: String| ConfigError {
+            path: path.to_path_buf(),
+            line,
+            message,
+        };
+        let keymap: toml::Table = toml::from_str(source).map_err(|err| {
+            let line = err.span().map(|span| line_of(source, span.start));
+            error(line, err.message().to_string())
+        })?;
+        let document = toml_edit::ImDocument::parse(source)
+            .map_err(|err| error(None, err.message().to_string()))?;
+        let line = |key: &str| {
+            document
+                .get(key)
+                .and_then(|item| item.span())
+                .map(|span| line_of(source, span.start))
+        };
+        for (name, value) in keymap {
+            let action = Action::from_name(&name)
+                .ok_or_else(|| error(line(&name), format!("unknown action `{name}`")))?;
+            let bindings: Vec<String> = value
+                .try_into()
+                .map_err(|_| error(line(&name), format!("`{name}` must be an array of keys")))?;
+            for binding in &bindings {
+                parse_binding(binding).map_err(|message| error(line(&name), message))?;
+            }
+            *self.keys.bindings_mut(action) = bindings;
+        }
+        Ok(())
+    }
+
+    /// Read the configuration file and the keymap next to it, the defaults are
+    /// used for files which do not exist
+    pub fn read() -> std::result::Result<Self, ConfigError> {
+        let path = config_path();
+        let mut config = match fs::read_to_string(&path) {
+            Ok(source) => Self::parse(&source, &path)?,
+            Err(_) => Self::default(),
+        };
+        let keymap = keymap_path();
+        if let Ok(source) = fs::read_to_string(&keymap) {
+            config.apply_keymap(&source, &keymap)?;
+        }
+        Ok(config)
+    }
+}
+
+/// Load the configuration, this should be done once before anything else
+pub fn load() -> Result<()> {
+    let config = Config::read()?;
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+/// The loaded configuration, or the default one if it was not loaded
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+/// Expand a leading `~` to the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ if path == "~" => dirs::home_dir().unwrap_or_default(),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Path of the configuration file
+///
+/// `config.toml` in the XDG config directory is used if it exists, then the
+/// one inside `~/.todo-cli`. If neither exists, the XDG location is used.
+pub fn config_path() -> PathBuf {
+    let xdg = dirs::config_dir().map(|dir| dir.join(CONFIG_FOLDER).join(CONFIG_NAME));
+    let home = dirs::home_dir().map(|dir| dir.join(HOME_FOLDER).join(CONFIG_NAME));
+    match (xdg, home) {
+        (Some(xdg), _) if xdg.exists() => xdg,
+        (_, Some(home)) if home.exists() => home,
+        (Some(xdg), _) => xdg,
+        (None, Some(home)) => home,
+        (None, None) => PathBuf::from(CONFIG_NAME),
+    }
+}
+
+/// Path of the keymap file, it is next to the configuration file
+pub fn keymap_path() -> PathBuf {
+    config_path().with_file_name(KEYMAP_NAME)
+}
+
+/// The effective value of a setting like `general.date_format`
+pub fn get(key: &str) -> Result<String> {
+    let value = toml::Value::try_from(config())?;
+    let mut current = &value;
+    for part in key.split('.') {
+        current = current
+            .get(part)
+            .ok_or_else(|| format!("`{key}` is not set or is not a setting"))?;
+    }
+    Ok(match current {
+        toml::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    })
+}
+
+/// Change a setting in the configuration file, keeping its comments
+///
+/// The value is taken as TOML if possible and as a string otherwise. The
+/// file is only written if the result is valid.
+pub fn set(key: &str, value: &str) -> Result<PathBuf> {
+    let path = config_path();
+    let source = fs::read_to_string(&path).unwrap_or_default();
+    let mut document: toml_edit::DocumentMut = source.parse()?;
+    let value = match value.parse::<toml_edit::Value>() {
+        Ok(value) => value,
+        Err(_) => toml_edit::Value::from(value),
+    };
+    let parts: Vec<&str> = key.split('.').collect();
+    let (last, sections) = parts.split_last().ok_or("empty setting name")?;
+    let mut table = document.as_table_mut();
+    for section in sections {
+        table = table
+            .entry(section)
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| format!("`{section}` is not a section"))?;
+    }
+    table.insert(last, toml_edit::value(value));
+    let updated = document.to_string();
+    Config::parse(&updated, &path)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, updated)?;
+    Ok(path)
+}
+
+/// Commented template written when the configuration is edited for the first time
+pub fn template() -> String {
+    let defaults = toml::to_string(&Config::default()).unwrap_or_default();
+    let mut template = String::from(
+        "# Configuration of todo-cli, uncomment a setting to change it\n\
+         # See `todo-cli config get <section.key>` for the value in effect\n",
+    );
+    for line in defaults.lines() {
+        if line.starts_with('[') || line.is_empty() {
+            template.push('\n');
+            template.push_str(line);
+            // settings without a default are not serialized
+            if line == "[storage]" {
+                template.push_str(
+                    "\n# path = \"~/.todo-cli\"\
+                     \n# backend = \"csv\"\
+                     \n# archive_after = \"30d\"\
+                     \n# keyfile = \"~/keys/todo.key\"\
+                     \n# autosave = \"change\"",
+                );
+            } else if line == "[theme]" {
+                template.push_str(
+                    "\n# dark, light, high-contrast, solarized, a theme from the themes folder or a file\
+                     \n# name = \"dark\"\
+                     \n# colors set here replace the ones of the theme\
+                     \n# border = \"blue\"\
+                     \n# date = \"#808080\"",
+                );
+            }
+        } else {
+            template.push_str("\n# ");
+            template.push_str(line);
+        }
+    }
+    template.push_str(
+        "\n\n# tabs of the TUI shown after the built-in ones, with the tasks matching all settings\
+         \n# [[views]]\
+         \n# name = \"Work\"\
+         \n# filter = \"pending\"\
+         \n# search = \"report\"\
+         \n# tags = [\"work\"]\
+         \n# project = \"office\"\
+         \n# due_within = \"7d\"",
+    );
+    template.push('\n');
+    template
+}
+
+/// Open the configuration file in an editor and check it afterwards
+///
+/// The file is created from a commented template if it does not exist
+pub fn edit() -> Result<()> {
+    let path = config_path();
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, template())?;
+    }
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // the editor may come with arguments, like `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("no editor is set")?;
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()?;
+    if !status.success() {
+        return Err("the editor exited with an error".into());
+    }
+    Config::parse(&fs::read_to_string(&path)?, &path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_parse() {
+        let path = Path::new(CONFIG_NAME);
+        let config = Config::parse(
+            "[general]\nsort = \"due\"\n\n[keys]\nquit = [\"ctrl-c\", \"q\"]\n",
+            path,
+        )
+        .unwrap();
+        assert_eq!(config.general.sort, SortBy::Due);
+        assert_eq!(config.general.date_format, "%d/%m, %H:%M");
+        assert_eq!(config.keys.quit, ["ctrl-c", "q"]);
+
+        let error = Config::parse("[general]\nconfirm = true\nsorting = \"due\"\n", path)
+            .err()
+            .unwrap();
+        assert_eq!(error.line, Some(3));
+        let error = Config::parse("[theme]\n\nborder = \"blurple\"\n", path)
+            .err()
+            .unwrap();
+        assert_eq!(error.line, Some(3));
+        assert!(error.to_string().contains("blurple"));
+
+        let source = "[[views]]\nname = \"Work\"\ntags = [\"work\"]\n\n[[views]]\nname = \"Soon\"\ndue_within = \"soon\"\n";
+        let error = Config::parse(source, path).err().unwrap();
+        assert_eq!(error.line, Some(7));
+        let config = Config::parse(&source.replace("soon\"", "3d\""), path).unwrap();
+        let task = Task {
+            tags: vec!["work".to_string(), "home".to_string()],
+            ..Default::default()
+        };
+        assert!(config.views[0].matches(&task));
+        assert!(!config.views[1].matches(&task));
+
+        let config = Config::parse("[storage]\nautosave = \"10s\"\n", path).unwrap();
+        assert_eq!(
+            config.storage.autosave(),
+            Autosave::Pause(std::time::Duration::from_secs(10))
+        );
+        let error = Config::parse("[storage]\nautosave = \"always\"\n", path)
+            .err()
+            .unwrap();
+        assert_eq!(error.line, Some(2));
+    }
+
+    #[test]
+    fn check_keymap() {
+        let path = Path::new(KEYMAP_NAME);
+        let mut config = Config::default();
+        config
+            .apply_keymap("delete = [\"D\"]\nup = [\"g g\", \"k\"]\n", path)
+            .unwrap();
+        assert_eq!(config.keys.delete, ["D"]);
+        assert_eq!(config.keys.up, ["g g", "k"]);
+        assert_eq!(config.keys.quit, ["q"]);
+
+        let error = config
+            .apply_keymap("quit = [\"q\"]\njump = [\"J\"]\n", path)
+            .err()
+            .unwrap();
+        assert_eq!(error.line, Some(2));
+        let error = config
+            .apply_keymap("quit = [\"hyper-q\"]\n", path)
+            .err()
+            .unwrap();
+        assert_eq!(error.line, Some(1));
+    }
+
+    #[test]
+    fn check_template_is_valid() {
+        let template = template();
+        assert!(Config::parse(&template, Path::new(CONFIG_NAME)).is_ok());
+    }
+}
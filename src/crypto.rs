@@ -0,0 +1,360 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    Key, XChaCha20Poly1305, XNonce,
+};
+
+use crate::{
+    backup::BACKUP_FOLDER,
+    config::{config, expand_home},
+    files::{
+        data_file, no_metadata, ARCHIVE_NAME, CSV_NAME, HISTORY_NAME, RECOVERY_NAME, TRASH_NAME,
+    },
+    oplog::OPS_FOLDER,
+    Result,
+};
+
+/// File marking the store as encrypted, it holds what is needed to derive the key
+pub(crate) const ENCRYPTION_NAME: &str = "encryption";
+/// Environment variable with the path of a keyfile to use instead of a
+/// passphrase, it takes precedence over `storage.keyfile` of the configuration
+const KEYFILE_VAR: &str = "TODO_CLI_KEYFILE";
+/// Start of every encrypted file, followed by the nonce and the ciphertext
+const MAGIC: &[u8] = b"todo-cli:enc:1\n";
+const NONCE_LEN: usize = 24;
+/// Plaintext encrypted into the marker file to check the key
+const CHECK: &[u8] = b"todo-cli";
+/// Number of times a wrong passphrase can be entered
+const ATTEMPTS: usize = 3;
+
+/// Key of the store, derived once per run
+static KEY: OnceLock<Key> = OnceLock::new();
+
+/// Where the key of the store comes from
+pub enum KeySource {
+    Passphrase,
+    /// a file whose contents are used as the passphrase
+    Keyfile(PathBuf),
+}
+
+/// Settings stored in the marker file
+struct Marker {
+    params: Params,
+    salt: Vec<u8>,
+    keyfile: bool,
+    /// `CHECK` encrypted with the key
+    check: Vec<u8>,
+}
+
+impl Marker {
+    fn read() -> Result<Self> {
+        let path = data_file(ENCRYPTION_NAME).ok_or_else(no_metadata)?;
+        let contents = fs::read_to_string(path)?;
+        let value = |key: &str| -> Result<&str> {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                .ok_or_else(|| format!("`{key}` is missing from the encryption settings").into())
+        };
+        let params = Params::new(
+            value("m_cost")?.parse()?,
+            value("t_cost")?.parse()?,
+            value("p_cost")?.parse()?,
+            Some(32),
+        )
+        .map_err(|err| err.to_string())?;
+        Ok(Self {
+            params,
+            salt: from_hex(value("salt")?)?,
+            keyfile: value("source")? == "keyfile",
+            check: from_hex(value("check")?)?,
+        })
+    }
+
+    fn write(&self) -> Result<()> {
+        let path = data_file(ENCRYPTION_NAME).ok_or_else(no_metadata)?;
+        let contents = format!(
+            "kdf=argon2id\nm_cost={}\nt_cost={}\np_cost={}\nsalt={}\nsource={}\ncheck={}\n",
+            self.params.m_cost(),
+            self.params.t_cost(),
+            self.params.p_cost(),
+            to_hex(&self.salt),
+            if self.keyfile {
+                "keyfile"
+            } else {
+                "passphrase"
+            },
+            to_hex(&self.check)
+        );
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Derive the key with the memory hard Argon2id
+    fn derive(&self, secret: &[u8]) -> Result<Key> {
+        let mut key = Key::default();
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+            .hash_password_into(secret, &self.salt, &mut key)
+            .map_err(|err| err.to_string())?;
+        Ok(key)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| {
+            Ok(u8::from_str_radix(
+                hex.get(idx..idx + 2).unwrap_or("x"),
+                16,
+            )?)
+        })
+        .collect()
+}
+
+fn encrypt_with(key: &Key, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = XChaCha20Poly1305::new(key)
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| "could not encrypt the tasks")?;
+    Ok([MAGIC, nonce.as_slice(), &ciphertext].concat())
+}
+
+fn decrypt_with(key: &Key, data: &[u8]) -> Result<Vec<u8>> {
+    let data = data.strip_prefix(MAGIC).ok_or("file is not encrypted")?;
+    if data.len() < NONCE_LEN {
+        return Err("encrypted file is cut short".into());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    Ok(XChaCha20Poly1305::new(key)
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "could not decrypt the tasks, the key is wrong or the file was modified")?)
+}
+
+/// Whether the store is encrypted
+pub fn is_encrypted() -> bool {
+    data_file(ENCRYPTION_NAME).is_some_and(|path| path.exists())
+}
+
+/// Read the secret the key is derived from
+fn read_secret(source: &KeySource, confirm: bool) -> Result<Vec<u8>> {
+    match source {
+        KeySource::Keyfile(path) => Ok(fs::read(path)?),
+        KeySource::Passphrase => {
+            let passphrase = rpassword::prompt_password("Passphrase: ")?;
+            if confirm && rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+                return Err("passphrases do not match".into());
+            }
+            if passphrase.is_empty() {
+                return Err("passphrase cannot be empty".into());
+            }
+            Ok(passphrase.into_bytes())
+        }
+    }
+}
+
+/// Derive the key of an encrypted store, asking for the passphrase if there is no keyfile
+///
+/// Does nothing if the store is not encrypted or already unlocked
+pub fn unlock() -> Result<()> {
+    if !is_encrypted() || KEY.get().is_some() {
+        return Ok(());
+    }
+    let marker = Marker::read()?;
+    let keyfile = std::env::var_os(KEYFILE_VAR)
+        .map(PathBuf::from)
+        .or_else(|| config().storage.keyfile.as_deref().map(expand_home));
+    let source = match keyfile {
+        Some(path) => KeySource::Keyfile(path),
+        None if marker.keyfile => {
+            return Err(format!(
+                "the tasks are encrypted with a keyfile, set {KEYFILE_VAR} or storage.keyfile"
+            )
+            .into())
+        }
+        None => KeySource::Passphrase,
+    };
+    for attempt in 1..=ATTEMPTS {
+        let key = marker.derive(&read_secret(&source, false)?)?;
+        if decrypt_with(&key, &marker.check).is_ok_and(|check| check == CHECK) {
+            let _ = KEY.set(key);
+            return Ok(());
+        }
+        if matches!(source, KeySource::Keyfile(_)) {
+            break;
+        }
+        if attempt < ATTEMPTS {
+            eprintln!("Wrong passphrase, try again");
+        }
+    }
+    Err("wrong passphrase or keyfile".into())
+}
+
+/// Decrypt the contents of a file from the store
+///
+/// Plain files are returned as they are, unless the store is encrypted: nothing
+/// can then be slipped into it without the key
+pub fn open(data: Vec<u8>) -> Result<Vec<u8>> {
+    if !data.starts_with(MAGIC) {
+        if is_encrypted() && !data.is_empty() {
+            return Err(
+                "a file of the encrypted store is in plain text, run decrypt then encrypt again"
+                    .into(),
+            );
+        }
+        return Ok(data);
+    }
+    open_earlier(data)
+}
+
+/// Decrypt the contents of a file from an earlier version of the store, plain
+/// files are returned as they are since they may predate the encryption
+pub fn open_earlier(data: Vec<u8>) -> Result<Vec<u8>> {
+    if !data.starts_with(MAGIC) {
+        return Ok(data);
+    }
+    let key = KEY
+        .get()
+        .ok_or("the tasks are encrypted, the passphrase is needed")?;
+    decrypt_with(key, &data)
+}
+
+/// Read a file from the store
+pub fn read(path: &Path) -> Result<Vec<u8>> {
+    open(fs::read(path)?)
+}
+
+/// Write a file to the store, encrypting it if the store is encrypted
+pub fn write(path: &Path, data: &[u8]) -> Result<()> {
+    if is_encrypted() {
+        let key = KEY
+            .get()
+            .ok_or("the tasks are encrypted, the passphrase is needed")?;
+        fs::write(path, encrypt_with(key, data)?)?;
+    } else {
+        fs::write(path, data)?;
+    }
+    Ok(())
+}
+
+/// Every file of the store holding tasks
+fn store_files() -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for name in [
+        CSV_NAME,
+        HISTORY_NAME,
+        TRASH_NAME,
+        ARCHIVE_NAME,
+        RECOVERY_NAME,
+    ] {
+        files.push(data_file(name).ok_or_else(no_metadata)?);
+    }
+    for folder in [BACKUP_FOLDER, OPS_FOLDER] {
+        let dir = data_file(folder).ok_or_else(no_metadata)?;
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir)? {
+                files.push(entry?.path());
+            }
+        }
+    }
+    files.retain(|path| path.extension().is_some_and(|ext| ext == "csv") && path.exists());
+    Ok(files)
+}
+
+/// Read every file of the store into memory
+fn read_store() -> Result<Vec<(PathBuf, Vec<u8>)>> {
+    store_files()?
+        .into_iter()
+        .map(|path| {
+            // a decryption cut short leaves some files in plain text
+            let data = open_earlier(fs::read(&path)?)?;
+            Ok((path, data))
+        })
+        .collect()
+}
+
+/// Encrypt every file of the store with a key from the given source
+pub fn encrypt_store(source: KeySource) -> Result<usize> {
+    if is_encrypted() {
+        return Err("the tasks are already encrypted".into());
+    }
+    let secret = read_secret(&source, true)?;
+    let mut salt = vec![0; 16];
+    OsRng.fill_bytes(&mut salt);
+    let mut marker = Marker {
+        params: Params::default(),
+        salt,
+        keyfile: matches!(source, KeySource::Keyfile(_)),
+        check: Vec::new(),
+    };
+    let key = marker.derive(&secret)?;
+    marker.check = encrypt_with(&key, CHECK)?;
+
+    // the encrypted files are written next to the plain ones and swapped in
+    // once the marker holding the salt is written, so that an interrupted
+    // encryption leaves either plain files or a store which can be decrypted
+    let files = read_store()?;
+    let mut temps = Vec::new();
+    for (path, data) in &files {
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, encrypt_with(&key, data)?)?;
+        temps.push((temp, path));
+    }
+    marker.write()?;
+    for (temp, path) in temps {
+        fs::rename(temp, path)?;
+    }
+    let _ = KEY.set(key);
+    Ok(files.len())
+}
+
+/// Store every file of the store in plain text again
+pub fn decrypt_store() -> Result<usize> {
+    if !is_encrypted() {
+        return Err("the tasks are not encrypted".into());
+    }
+    unlock()?;
+    let files = read_store()?;
+    // the marker goes last, so that an interrupted decryption can be run again
+    for (path, data) in &files {
+        fs::write(path, data)?;
+    }
+    fs::remove_file(data_file(ENCRYPTION_NAME).ok_or_else(no_metadata)?)?;
+    Ok(files.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_round_trip() {
+        let marker = Marker {
+            params: Params::new(64, 1, 1, Some(32)).unwrap(),
+            salt: vec![7; 16],
+            keyfile: false,
+            check: Vec::new(),
+        };
+        let key = marker.derive(b"secret").unwrap();
+        let data = encrypt_with(&key, b"id,desc\n1,customer").unwrap();
+        assert!(!data.windows(8).any(|window| window == b"customer"));
+        assert_eq!(decrypt_with(&key, &data).unwrap(), b"id,desc\n1,customer");
+
+        let wrong = marker.derive(b"wrong").unwrap();
+        assert!(decrypt_with(&wrong, &data).is_err());
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(decrypt_with(&key, &tampered).is_err());
+        assert_eq!(from_hex(&to_hex(&[0, 15, 255])).unwrap(), [0, 15, 255]);
+    }
+}
@@ -1,86 +1,398 @@
-use crossterm::event::{Event, KeyCode, KeyEventKind};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use ratatui::layout::Rect;
 
 use crate::{
     app::{App, Status},
-    state::State,
+    config::config,
+    form::{BatchField, TaskForm},
+    input::Input,
+    keymap::Action,
+    search,
+    state::{State, Task},
+    ui::GLYPH_COLUMN,
     Result,
 };
 
+/// Time within which a second click on the same task edits it
+const DOUBLE_CLICK: Duration = Duration::from_millis(500);
+
 /// Managing all the events occuring in idle state of the app
-fn manage_idle_events(app: &mut App, state: &mut State, key: KeyCode) {
-    match key {
-        // quit
-        KeyCode::Char('q') => {
+///
+/// Keys are bound to actions by the keymap
+fn manage_idle_events(app: &mut App, state: &mut State, key: KeyEvent) -> Option<bool> {
+    let Some(action) = app.keymap.press(key) else {
+        // number keys which are not bound pick a tab
+        if let KeyCode::Char(digit @ '1'..='9') = key.code {
+            if key.modifiers.is_empty() {
+                app.switch_tab(digit as usize - '1' as usize, state);
+            }
+        }
+        return None;
+    };
+    perform_action(app, state, action)
+}
+
+/// Do what an action of the task list stands for, whether it came from a key or a click
+fn perform_action(app: &mut App, state: &mut State, action: Action) -> Option<bool> {
+    match action {
+        Action::Quit => {
+            if !config().general.confirm {
+                return Some(true);
+            }
             app.switch_status(Status::Exiting);
         }
-        // new task
-        KeyCode::Char('i') => app.switch_status(Status::Editing {
-            edit: String::new(),
+        Action::NewTask => app.switch_status(Status::Editing {
+            edit: Box::default(),
             previous: None,
         }),
-        // delete task
-        KeyCode::Char('d') | KeyCode::Char('x') => {
-            let idx = state.selected;
-            if let Some(idx) = idx {
-                state.remove_task_by_seq(idx);
-            }
-        }
-        // move down
-        KeyCode::Down | KeyCode::Char('j') => state.move_selection(false),
-        // moev up
-        KeyCode::Up | KeyCode::Char('k') => state.move_selection(true),
-        // edit the task
-        KeyCode::Char('e') => {
-            let idx = state.selected;
-            if let Some(idx) = idx {
+        Action::Delete => {
+            state.remove_tasks(&state.targets());
+            app.visual = None;
+        }
+        Action::MoveDown => app.move_selection_by(state, 1),
+        Action::MoveUp => app.move_selection_by(state, -1),
+        Action::PageDown => app.move_selection_by(state, app.page as isize),
+        Action::PageUp => app.move_selection_by(state, -(app.page as isize)),
+        Action::Top => app.select_row(state, 0),
+        Action::Bottom => app.select_row(state, usize::MAX),
+        Action::Edit => {
+            if let Some(idx) = state.cursor {
                 if idx >= state.ids.len() {
-                    return;
+                    return None;
                 }
                 app.switch_status(Status::Editing {
-                    edit: state.tasks.get(&state.ids[idx]).unwrap().task.desc.clone(),
+                    edit: Box::new(TaskForm::from_task(
+                        &state.tasks.get(&state.ids[idx]).unwrap().task,
+                    )),
                     previous: Some(idx),
                 });
             }
         }
-        // mark task complete
-        KeyCode::Enter => {
-            let idx = state.selected;
-            if let Some(idx) = idx {
-                if idx > state.ids.len() {
-                    return;
+        Action::Redo => {
+            state.redo();
+        }
+        Action::Trash => app.switch_status(Status::Trash { selected: 0 }),
+        Action::NextTheme => app.next_theme(),
+        Action::Details => app.details = !app.details,
+        Action::NextTab => app.switch_tab((app.tab + 1) % app.tabs.len(), state),
+        Action::PrevTab => app.switch_tab((app.tab + app.tabs.len() - 1) % app.tabs.len(), state),
+        Action::Search => app.switch_status(Status::Searching {
+            query: Input::default(),
+        }),
+        action @ (Action::NextMatch | Action::PrevMatch) => {
+            let query = app.search.as_deref()?;
+            let forward = action == Action::NextMatch;
+            let visible = app.visible(state);
+            if let Some(idx) = search::next_match(state, &visible, query, state.cursor, forward) {
+                state.select(idx);
+            }
+        }
+        Action::Undo => {
+            state.undo();
+        }
+        Action::Toggle => {
+            let targets = state.targets();
+            // marked tasks all end up completed unless they already were
+            let complete = targets
+                .iter()
+                .any(|id| state.tasks.get(id).is_some_and(|item| !item.task.completed));
+            state.update_tasks(&targets, |task| {
+                if complete && !task.completed {
+                    task.mark_complete();
+                } else if !complete {
+                    task.mark_incomplete();
+                }
+            });
+        }
+        Action::Mark => {
+            if let Some(id) = state.cursor.and_then(|idx| state.ids.get(idx)).copied() {
+                state.toggle_mark(id);
+            }
+        }
+        Action::Visual => {
+            if app.visual.take().is_none() {
+                if state.cursor.is_none() {
+                    app.select_row(state, 0);
+                }
+                if let Some(id) = state.cursor.and_then(|idx| state.ids.get(idx)) {
+                    app.visual = Some((*id, state.marked.clone()));
+                }
+            }
+        }
+        Action::Unmark => {
+            app.visual = None;
+            state.marked.clear();
+        }
+        action @ (Action::Tag | Action::Project | Action::Priority) => {
+            let targets = state.targets();
+            let field = match action {
+                Action::Tag => BatchField::Tags,
+                Action::Project => BatchField::Project,
+                _ => BatchField::Priority,
+            };
+            // the current value is shown when a single task is changed
+            let current = match (&targets[..], field) {
+                ([id], BatchField::Project) => state.tasks.get(id)?.task.project.clone(),
+                ([id], BatchField::Priority) => {
+                    state.tasks.get(id)?.task.priority.map(String::from)
                 }
-                state.toggle_task_status(idx);
+                _ => None,
+            };
+            if !targets.is_empty() {
+                app.visual = None;
+                app.switch_status(Status::Prompt {
+                    field,
+                    input: Input::new(&current.unwrap_or_default()),
+                    error: None,
+                });
+            }
+        }
+        // only used in the trash
+        Action::Restore | Action::Purge => {}
+    }
+    None
+}
+
+/// Managing a click on the task list
+///
+/// A click selects a task, or marks it along with Ctrl, and a second click on
+/// the same task edits it. Clicking the glyph of a task toggles only that task
+fn manage_list_click(app: &mut App, state: &mut State, mouse: MouseEvent) -> Option<bool> {
+    let row = app.offset + (mouse.row - app.list_area.y) as usize;
+    let Some(idx) = app.visible(state).get(row).copied() else {
+        app.last_click = None;
+        return None;
+    };
+    let id = state.ids[idx];
+    if mouse.column < app.list_area.x + GLYPH_COLUMN {
+        app.last_click = None;
+        state.update_tasks(&[id], |task| {
+            if task.completed {
+                task.mark_incomplete();
+            } else {
+                task.mark_complete();
+            }
+        });
+        return None;
+    }
+    state.select(idx);
+    if mouse.modifiers.contains(KeyModifiers::CONTROL) {
+        app.last_click = None;
+        state.toggle_mark(id);
+        return None;
+    }
+    let double = app
+        .last_click
+        .is_some_and(|(time, last)| last == id && time.elapsed() < DOUBLE_CLICK);
+    if double {
+        app.last_click = None;
+        return perform_action(app, state, Action::Edit);
+    }
+    app.last_click = Some((Instant::now(), id));
+    None
+}
+
+/// Managing the mouse, which is only used while browsing the task list or the trash
+///
+/// Tabs and the actions of the footer are clicked to use them, and the wheel
+/// moves the selection
+fn manage_mouse_events(app: &mut App, state: &mut State, mouse: MouseEvent) -> Option<bool> {
+    let at = Rect::new(mouse.column, mouse.row, 1, 1);
+    match (&app.status, mouse.kind) {
+        (Status::Trash { .. }, MouseEventKind::ScrollDown) => {
+            manage_trash_events(app, state, Action::MoveDown)
+        }
+        (Status::Trash { .. }, MouseEventKind::ScrollUp) => {
+            manage_trash_events(app, state, Action::MoveUp)
+        }
+        (Status::Idle, MouseEventKind::ScrollDown) => app.move_selection_by(state, 1),
+        (Status::Idle, MouseEventKind::ScrollUp) => app.move_selection_by(state, -1),
+        (Status::Idle, MouseEventKind::Down(MouseButton::Left)) => {
+            if let Some(tab) = app.tab_areas.iter().position(|area| area.intersects(at)) {
+                app.switch_tab(tab, state);
+            } else if let Some((_, action)) =
+                app.buttons.iter().find(|(area, _)| area.intersects(at))
+            {
+                return perform_action(app, state, *action);
+            } else if app.list_area.intersects(at) {
+                return manage_list_click(app, state, mouse);
             }
         }
         _ => {}
     }
+    None
 }
 
-/// Managing all the events in editing state of the app
-fn manage_edit_events(app: &mut App, state: &mut State, key: KeyCode) {
-    match key {
+/// Managing all the events while typing a field of the marked tasks
+fn manage_prompt_events(app: &mut App, state: &mut State, key: KeyEvent) {
+    let Status::Prompt {
+        field,
+        input,
+        error,
+    } = &mut app.status
+    else {
+        return;
+    };
+    match key.code {
         KeyCode::Esc => app.switch_status(Status::Idle),
         KeyCode::Enter => {
-            let task = app.get_editing_task();
-            if task.trim().is_empty() {
+            let (field, text) = (*field, input.text().to_string());
+            // the text is checked once rather than for every task
+            if let Err(message) = field.apply(&text, &mut Task::default()) {
+                *error = Some(message);
                 return;
             }
+            state.update_tasks(&state.targets(), |task| {
+                let _ = field.apply(&text, task);
+            });
+            app.switch_status(Status::Idle);
+        }
+        _ => {
+            *error = None;
+            input.handle(key);
+        }
+    }
+}
+
+/// Managing all the events in editing state of the app
+fn manage_edit_events(app: &mut App, state: &mut State, key: KeyEvent) {
+    let newline = key
+        .modifiers
+        .intersects(KeyModifiers::SHIFT | KeyModifiers::ALT);
+    match key.code {
+        KeyCode::Esc => app.switch_status(Status::Idle),
+        KeyCode::Enter if !newline => {
             let prev = app.get_prev_task();
-            if let Some(idx) = prev {
+            let Some(form) = app.form_mut() else {
+                return;
+            };
+            let mut task = match prev.and_then(|idx| state.tasks.get(state.ids.get(idx)?)) {
                 // editing already existing task
-                state.remove_task_by_seq(idx)
+                Some(item) => item.task.clone(),
+                None => Task::default(),
+            };
+            if let Err(error) = form.apply(&mut task) {
+                form.error = Some(error);
+                return;
+            }
+            if prev.is_some() {
+                state.update_task(task);
+            } else {
+                state.insert_task(task);
             }
-            state.add_task(&task);
             app.switch_status(Status::Idle)
         }
-        KeyCode::Char(ch) => {
-            app.add_char(ch);
+        _ => {
+            if let Some(form) = app.form_mut() {
+                form.error = None;
+                form.handle(key);
+            }
+        }
+    }
+}
+
+/// Managing all the events while typing a search
+///
+/// The best match is selected as the query changes, Up and Down move through
+/// the matches. Enter keeps the query for `n` and `N` while Esc drops it,
+/// both go back to the full list with the selection kept
+fn manage_search_events(app: &mut App, state: &mut State, key: KeyEvent) {
+    let visible = app.visible(state);
+    let Status::Searching { query } = &mut app.status else {
+        return;
+    };
+    match key.code {
+        KeyCode::Esc => {
+            app.search = None;
+            app.switch_status(Status::Idle);
+        }
+        KeyCode::Enter => {
+            app.search = Some(query.text().to_string()).filter(|query| !query.is_empty());
+            app.switch_status(Status::Idle);
+        }
+        KeyCode::Up | KeyCode::Down => {
+            let matches = search::find(state, &visible, query.text());
+            let current = matches
+                .iter()
+                .position(|found| Some(found.idx) == state.cursor);
+            let next = match (current, key.code) {
+                (Some(pos), KeyCode::Up) => pos.saturating_sub(1),
+                (Some(pos), _) => pos + 1,
+                (None, _) => 0,
+            };
+            if let Some(found) = matches.get(next) {
+                state.select(found.idx);
+            }
+        }
+        _ => {
+            if query.handle(key) {
+                if let Some(best) = search::find(state, &visible, query.text()).first() {
+                    state.select(best.idx);
+                }
+            }
+        }
+    }
+}
+
+/// Managing all the events while browsing the trash
+fn manage_trash_events(app: &mut App, state: &mut State, action: Action) {
+    let Status::Trash { selected } = app.status else {
+        return;
+    };
+    let last = state.trash.len().saturating_sub(1);
+    match action {
+        Action::Trash | Action::Quit => app.switch_status(Status::Idle),
+        Action::MoveDown => app.switch_status(Status::Trash {
+            selected: (selected + 1).min(last),
+        }),
+        Action::MoveUp => app.switch_status(Status::Trash {
+            selected: selected.saturating_sub(1),
+        }),
+        Action::Top => app.switch_status(Status::Trash { selected: 0 }),
+        Action::Bottom => app.switch_status(Status::Trash { selected: last }),
+        Action::Restore => {
+            if let Some(trashed) = state.trash.get(selected) {
+                state.restore_task(&trashed.task.id.clone());
+            }
         }
-        KeyCode::Backspace => {
-            app.pop_char();
+        Action::Purge => {
+            if let Some(trashed) = state.trash.get(selected) {
+                state.purge_task(&trashed.task.id.clone());
+            }
         }
         _ => {}
     }
+    if let Status::Trash { selected } = &mut app.status {
+        *selected = (*selected).min(state.trash.len().saturating_sub(1));
+    }
+}
+
+/// Managing all the events while settling the tasks changed both here and in the data file
+///
+/// `m` keeps the version of the TUI and `t` takes the one of the file, `M` and
+/// `T` do so for every task left
+fn manage_conflict_events(app: &mut App, state: &mut State, key: KeyCode) {
+    let Status::Conflict { conflicts } = &mut app.status else {
+        return;
+    };
+    let settled = match key {
+        KeyCode::Char('m' | 't') => 1,
+        KeyCode::Char('M' | 'T') => conflicts.len(),
+        _ => return,
+    };
+    let step: Vec<_> = conflicts
+        .drain(..settled)
+        .filter(|_| matches!(key, KeyCode::Char('t' | 'T')))
+        .filter_map(|conflict| conflict.take_theirs())
+        .collect();
+    state.apply_unrecorded(&step);
+    if conflicts.is_empty() {
+        app.switch_status(Status::Idle);
+    }
 }
 
 /// Managing all the events in exiting state of the app
@@ -99,23 +411,58 @@ fn manage_exiting_events(app: &mut App, key: KeyCode) -> Option<bool> {
 }
 
 /// Local hepler function made for improving modularity of main function
-fn helper(app: &mut App, state: &mut State, key: KeyCode) -> Option<bool> {
-    match app.status {
-        Status::Idle => {
-            manage_idle_events(app, state, key);
-        }
-        Status::Editing {
-            edit: _,
-            previous: _,
-        } => {
+fn helper(app: &mut App, state: &mut State, event: Event) -> Option<bool> {
+    if let Event::Key(_)
+    | Event::Mouse(MouseEvent {
+        kind: MouseEventKind::Down(_),
+        ..
+    }) = event
+    {
+        app.notice = None;
+    }
+    let quit = match (event, &app.status) {
+        (Event::Mouse(mouse), _) => manage_mouse_events(app, state, mouse),
+        // press for single keypress in windows
+        (Event::Key(key), _) if key.kind != KeyEventKind::Press => None,
+        (Event::Key(key), Status::Idle) => manage_idle_events(app, state, key),
+        (
+            Event::Key(key),
+            Status::Editing {
+                edit: _,
+                previous: _,
+            },
+        ) => {
             manage_edit_events(app, state, key);
+            None
         }
-        Status::Exiting => {
-            return manage_exiting_events(app, key);
+        (Event::Key(key), Status::Searching { .. }) => {
+            manage_search_events(app, state, key);
+            None
         }
-    }
-
-    None
+        (Event::Key(key), Status::Prompt { .. }) => {
+            manage_prompt_events(app, state, key);
+            None
+        }
+        (Event::Key(key), Status::Trash { .. }) => {
+            // esc always leaves the trash
+            if key.code == KeyCode::Esc {
+                app.switch_status(Status::Idle);
+            } else if let Some(action) = app.keymap.press(key) {
+                manage_trash_events(app, state, action);
+            }
+            None
+        }
+        (Event::Key(key), Status::Exiting) => manage_exiting_events(app, key.code),
+        (Event::Key(key), Status::Conflict { .. }) => {
+            manage_conflict_events(app, state, key.code);
+            None
+        }
+        _ => None,
+    };
+    // a changed task may have left the current tab
+    app.fix_selection(state);
+    app.update_visual(state);
+    quit
 }
 
 /// Handles all the inputs fromt the user
@@ -125,11 +472,22 @@ fn helper(app: &mut App, state: &mut State, key: KeyCode) -> Option<bool> {
 /// when the loop must break
 pub fn handle_events(app: &mut App, state: &mut State) -> Result<Option<bool>> {
     if crossterm::event::poll(std::time::Duration::from_millis(250))? {
-        if let Event::Key(k) = crossterm::event::read()? {
-            // press for single keypress in windows
-            if k.kind == KeyEventKind::Press {
-                return Ok(helper(app, state, k.code));
+        match crossterm::event::read()? {
+            // pasted text arrives at once instead of as key presses
+            Event::Paste(text) => {
+                let visible = app.visible(state);
+                if let Some(form) = app.form_mut() {
+                    form.focused().insert_str(&text);
+                } else if let Status::Prompt { input, .. } = &mut app.status {
+                    input.insert_str(&text);
+                } else if let Status::Searching { query } = &mut app.status {
+                    query.insert_str(&text);
+                    if let Some(best) = search::find(state, &visible, query.text()).first() {
+                        state.select(best.idx);
+                    }
+                }
             }
+            event => return Ok(helper(app, state, event)),
         }
     }
     Ok(None)
@@ -1,104 +1,777 @@
+use chrono::{DateTime, Local, SubsecRound};
 use std::{
-    fs::{self, File},
+    collections::HashSet,
+    fs,
     io::{Error, ErrorKind},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
-use csv::ReaderBuilder;
+use csv::{ReaderBuilder, StringRecord};
 
 use crate::{
-    state::{State, Task},
-    Result,
+    backup::{snapshot, BACKUP_FOLDER},
+    config::{config, expand_home, CONFIG_NAME},
+    crypto::{self, ENCRYPTION_NAME},
+    formats::{Format, DEPENDS_KEY},
+    history::{History, Operation, Step},
+    keymap::KEYMAP_NAME,
+    oplog::{self, DEVICE_NAME, OPS_FOLDER},
+    parse_age,
+    state::{ArchivedTask, State, Task, TrashedTask},
+    theme::THEMES_FOLDER,
+    Id, Result,
 };
 
 const FOLDER_NAME: &str = ".todo-cli";
-const CSV_NAME: &str = ".todo-cli/data.csv";
+pub(crate) const CSV_NAME: &str = "data.csv";
+pub(crate) const HISTORY_NAME: &str = "history.csv";
+pub(crate) const TRASH_NAME: &str = "trash.csv";
+pub(crate) const ARCHIVE_NAME: &str = "archive.csv";
+/// Tasks of a TUI session which ended before they could be saved
+pub(crate) const RECOVERY_NAME: &str = "recovery.csv";
+
+/// Environment variable holding the age after which completed tasks get archived,
+/// it takes precedence over `storage.archive_after` of the configuration
+const AUTO_ARCHIVE_VAR: &str = "TODO_CLI_ARCHIVE_AFTER";
+
+/// Columns used to store a single task
+///
+/// Columns after `updated` were added later and are optional while reading
+pub(crate) const TASK_HEADER: [&str; 13] = [
+    "id",
+    "desc",
+    "status",
+    "updated",
+    "priority",
+    "created",
+    "completed_on",
+    "due",
+    "project",
+    "tags",
+    "extra",
+    "notes",
+    "depends",
+];
+/// The storage directory, `~/.todo-cli` unless configured otherwise
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    match &config().storage.path {
+        Some(path) => Some(expand_home(path)),
+        None => dirs::home_dir().map(|home| home.join(FOLDER_NAME)),
+    }
+}
+
+/// Path of a file inside the storage directory
+pub(crate) fn data_file(name: &str) -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(name))
+}
+
+pub(crate) fn no_metadata() -> Box<dyn std::error::Error> {
+    Box::new(Error::new(ErrorKind::NotFound, "No metadata found"))
+}
+
+/// Convert a task into the columns of a csv record
+pub(crate) fn task_record(task: &Task) -> Vec<String> {
+    let date = |date: &Option<DateTime<Local>>| date.map(|date| date.to_string());
+    vec![
+        task.id.to_string(),
+        task.desc.clone(),
+        task.completed.to_string(),
+        task.last_updated.to_string(),
+        task.priority.map(String::from).unwrap_or_default(),
+        date(&task.created).unwrap_or_default(),
+        date(&task.completed_on).unwrap_or_default(),
+        date(&task.due).unwrap_or_default(),
+        task.project.clone().unwrap_or_default(),
+        task.tags.join(" "),
+        if task.extra.is_empty() {
+            String::new()
+        } else {
+            serde_json::to_string(&task.extra).unwrap_or_default()
+        },
+        task.notes.clone(),
+        task.depends
+            .iter()
+            .map(Id::to_string)
+            .collect::<Vec<_>>()
+            .join(" "),
+    ]
+}
+
+/// Read a task from the columns of a csv record starting at `skip`
+pub(crate) fn task_from_record(record: &StringRecord, skip: usize) -> Result<Task> {
+    let field = |idx: usize| record.get(skip + idx).unwrap_or_default();
+    let date = |idx: usize| -> Result<Option<DateTime<Local>>> {
+        match field(idx) {
+            "" => Ok(None),
+            date => Ok(Some(date.parse()?)),
+        }
+    };
+    let text = |idx: usize| Some(field(idx).to_string()).filter(|text| !text.is_empty());
+    Ok(Task {
+        id: field(0).parse()?,
+        desc: field(1).to_string(),
+        completed: field(2).parse()?,
+        last_updated: field(3).parse()?,
+        priority: field(4).chars().next(),
+        created: date(5)?,
+        completed_on: date(6)?,
+        due: date(7)?,
+        project: text(8),
+        tags: field(9).split_whitespace().map(String::from).collect(),
+        extra: match field(10) {
+            "" => Default::default(),
+            extra => serde_json::from_str(extra)?,
+        },
+        notes: field(11).to_string(),
+        depends: field(12)
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<std::result::Result<_, _>>()?,
+    })
+}
 
 /// Checks whether data for tasks already exists
 pub fn check_existing_metadata() -> bool {
-    match dirs::home_dir() {
-        Some(dir) => dir.join(Path::new(FOLDER_NAME)).exists(),
-        None => false,
-    }
+    data_dir().is_some_and(|dir| dir.exists())
 }
 
 /// Create storage in home directory
 pub fn create_metadata() -> Result<()> {
-    if let Some(home) = dirs::home_dir() {
-        fs::create_dir(home.join(Path::new(FOLDER_NAME)))?;
+    if let Some(dir) = data_dir() {
+        fs::create_dir_all(dir)?;
     }
     Ok(())
 }
 
 /// Serialize and enter the data to the file
-pub fn enter_data_to_file(state: &State) -> Result<()> {
-    if let Some(home) = dirs::home_dir() {
-        let tasks_as_vec = state.get_tasks();
-        let mut writer = csv::Writer::from_path(home.join(Path::new(CSV_NAME)))?;
-
-        // header
-        writer.write_record(["id", "desc", "status", "updated"])?;
-
-        // contents
-        for task in tasks_as_vec {
-            writer.write_record(&[
-                task.id.to_string(),
-                task.desc.clone(),
-                task.completed.to_string(),
-                task.last_updated.to_string(),
-            ])?;
-        }
-
-        writer.flush()?;
-        Ok(())
+///
+/// The previous contents are kept as a backup snapshot
+pub fn enter_data_to_file(state: &mut State) -> Result<()> {
+    let path = data_file(CSV_NAME).ok_or_else(no_metadata)?;
+    snapshot()?;
+    archive_expired(state)?;
+    // the data file holds the tasks as last seen by this device
+    let seen = if oplog::enabled() && path.exists() {
+        read_tasks_from(&path)?
     } else {
-        Err(Box::new(Error::new(
-            ErrorKind::NotFound,
-            "No metadata found",
-        )))
+        Vec::new()
+    };
+    write_data(state, &seen)
+}
+
+/// Write the tasks, the history and the trash without taking a snapshot first
+///
+/// In oplog mode the changes from `seen`, the tasks as this device last read
+/// them, are recorded in its log
+pub fn write_data(state: &State, seen: &[Task]) -> Result<()> {
+    let path = data_file(CSV_NAME).ok_or_else(no_metadata)?;
+    if oplog::enabled() {
+        let after: Vec<Task> = state.get_tasks().into_iter().cloned().collect();
+        let removed: Vec<&Task> = seen
+            .iter()
+            .filter(|task| after.iter().all(|other| other.id != task.id))
+            .collect();
+        // tasks moved to the archive were not deleted by the user
+        let archived: Vec<Id> = if removed.is_empty() {
+            Vec::new()
+        } else {
+            let archive = read_archive()?;
+            removed
+                .into_iter()
+                .filter(|task| archive.iter().any(|archived| archived.task == **task))
+                .map(|task| task.id)
+                .collect()
+        };
+        oplog::record(seen, &after, &archived)?;
     }
+    crypto::write(&path, &tasks_to_csv(state.get_tasks())?)?;
+    write_history(&state.history)?;
+    write_trash(&state.trash)?;
+    Ok(())
+}
+
+/// Serialize tasks in the format of the data file
+pub(crate) fn tasks_to_csv<'a>(tasks: impl IntoIterator<Item = &'a Task>) -> Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    // header
+    writer.write_record(TASK_HEADER)?;
+
+    // contents
+    for task in tasks {
+        writer.write_record(task_record(task))?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+/// Deserialize tasks in the format of the data file
+pub(crate) fn tasks_from_csv(data: &[u8]) -> Result<Vec<Task>> {
+    let mut reader = ReaderBuilder::new().from_reader(data);
+    let mut tasks = Vec::new();
+    for record in reader.records() {
+        tasks.push(task_from_record(&record?, 0)?);
+    }
+    Ok(tasks)
+}
+
+/// Read the tasks stored in a data file
+pub(crate) fn read_tasks_from(path: &Path) -> Result<Vec<Task>> {
+    tasks_from_csv(&crypto::read(path)?)
 }
 
 /// Deserialize daat from the given file
 pub fn read_data_from_file() -> Result<State> {
-    if let Some(home) = dirs::home_dir() {
-        let mut state = State::new();
-        let file = File::open(home.join(Path::new(CSV_NAME)))?;
-        let mut reader = ReaderBuilder::new().from_reader(file);
-        for record in reader.records() {
-            let record = record?;
-            let task: Task = record.deserialize(None)?;
-            let id = task.id;
-            state.tasks.insert(
-                id,
-                crate::state::ListItem {
-                    task,
-                    selected: false,
-                },
-            );
-            state.ids.push(id);
+    let path = data_file(CSV_NAME).ok_or_else(no_metadata)?;
+    let mut state = State::new();
+    let tasks = if oplog::enabled() {
+        // the backend was switched in the configuration, start from the data file
+        if !oplog::started() {
+            let tasks = if path.exists() {
+                read_tasks_from(&path)?
+            } else {
+                Vec::new()
+            };
+            oplog::enable(&tasks)?;
+        }
+        let tasks = oplog::load()?;
+        // keep the data file in step so changes are diffed against what was loaded
+        let data = tasks_to_csv(&tasks)?;
+        if crypto::read(&path).ok().as_ref() != Some(&data) {
+            crypto::write(&path, &data)?;
         }
-        Ok(state)
+        tasks
+    } else if path.exists() {
+        read_tasks_from(&path)?
     } else {
-        Err(Box::new(Error::new(
-            ErrorKind::NotFound,
-            "No metadata found",
-        )))
+        return Ok(state);
+    };
+    for task in tasks {
+        let id = task.id;
+        state.tasks.insert(
+            id,
+            crate::state::ListItem {
+                task,
+                selected: false,
+            },
+        );
+        state.ids.push(id);
+    }
+    state.history = read_history()?;
+    state.trash = read_trash()?;
+    state.reserved = read_archive()?
+        .into_iter()
+        .map(|archived| archived.task.id)
+        .collect();
+    Ok(state)
+}
+
+/// Read the tasks, the history and the trash as they are stored
+///
+/// Unlike [`read_data_from_file`] nothing is written: the data file is not
+/// brought in step with the logs and no task is archived
+pub fn read_stored_data() -> Result<State> {
+    let path = data_file(CSV_NAME).ok_or_else(no_metadata)?;
+    let tasks = if oplog::enabled() && oplog::started() {
+        oplog::load()?
+    } else if path.exists() {
+        read_tasks_from(&path)?
+    } else {
+        Vec::new()
+    };
+    let mut state = State::from_tasks(tasks);
+    state.history = read_history()?;
+    state.trash = read_trash()?;
+    Ok(state)
+}
+
+/// Keep the tasks of a TUI session which ended abruptly so that they are restored on the next launch
+///
+/// Nothing is written when they match the data file, returns whether they were kept
+pub fn write_recovery(state: &State) -> Result<bool> {
+    let path = data_file(CSV_NAME).ok_or_else(no_metadata)?;
+    let saved = if path.exists() {
+        read_tasks_from(&path)?
+    } else {
+        Vec::new()
+    };
+    if state.changes_to(&saved).is_empty() {
+        return Ok(false);
+    }
+    create_metadata()?;
+    let recovery = data_file(RECOVERY_NAME).ok_or_else(no_metadata)?;
+    crypto::write(&recovery, &tasks_to_csv(state.get_tasks())?)?;
+    Ok(true)
+}
+
+/// Tasks kept by a TUI session which ended abruptly, if there was one
+pub fn read_recovery() -> Result<Option<Vec<Task>>> {
+    match data_file(RECOVERY_NAME) {
+        Some(path) if path.exists() => Ok(Some(read_tasks_from(&path)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Forget the tasks kept by a TUI session which ended abruptly
+pub fn remove_recovery() -> Result<()> {
+    match data_file(RECOVERY_NAME) {
+        Some(path) if path.exists() => Ok(fs::remove_file(path)?),
+        _ => Ok(()),
     }
 }
 
+/// Completed tasks last updated before this time are archived automatically
+///
+/// Disabled unless an age like `14d` is set in `TODO_CLI_ARCHIVE_AFTER`
+fn auto_archive_expiry() -> Option<DateTime<Local>> {
+    let age = std::env::var(AUTO_ARCHIVE_VAR)
+        .ok()
+        .or_else(|| config().storage.archive_after.clone())?;
+    parse_age(&age).ok().map(|age| Local::now() - age)
+}
+
+/// Move the completed tasks which are older than the configured age to the archive
+///
+/// returns the number of tasks archived
+pub fn archive_expired(state: &mut State) -> Result<usize> {
+    let Some(expiry) = auto_archive_expiry() else {
+        return Ok(0);
+    };
+    let expired: Vec<Id> = state
+        .get_tasks()
+        .into_iter()
+        .filter(|task| task.completed && task.last_updated < expiry)
+        .map(|task| task.id)
+        .collect();
+    if expired.is_empty() {
+        return Ok(0);
+    }
+    let tasks = state.detach_tasks(&expired);
+    archive_tasks(tasks)?;
+    state.reserved.extend(expired.iter().copied());
+    Ok(expired.len())
+}
+
+/// Read all the tasks which were moved to the archive
+pub fn read_archive() -> Result<Vec<ArchivedTask>> {
+    let path = data_file(ARCHIVE_NAME).ok_or_else(no_metadata)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut archive = Vec::new();
+    let data = crypto::read(&path)?;
+    let mut reader = ReaderBuilder::new().from_reader(&data[..]);
+    for record in reader.records() {
+        let record = record?;
+        archive.push(ArchivedTask {
+            archived: record[0].parse()?,
+            task: task_from_record(&record, 1)?,
+        });
+    }
+    Ok(archive)
+}
+
+/// Add tasks to an archive, tasks already present are not duplicated
+///
+/// A task is only skipped when the archive holds an identical copy of it, a
+/// task which merely reuses the id of an archived one is kept
+fn add_to_archive(archive: &mut Vec<ArchivedTask>, tasks: Vec<Task>) {
+    for task in tasks {
+        if archive.iter().all(|archived| archived.task != task) {
+            archive.push(ArchivedTask {
+                task,
+                archived: Local::now(),
+            });
+        }
+    }
+}
+
+/// Add tasks to the archive, tasks already present are not duplicated
+///
+/// returns the number of newly archived tasks
+pub fn archive_tasks(tasks: Vec<Task>) -> Result<usize> {
+    let mut archive = read_archive()?;
+    let before = archive.len();
+    add_to_archive(&mut archive, tasks);
+
+    let path = data_file(ARCHIVE_NAME).ok_or_else(no_metadata)?;
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut header = vec!["archived"];
+    header.extend(TASK_HEADER);
+    writer.write_record(header)?;
+    for archived in &archive {
+        let mut record = vec![archived.archived.to_string()];
+        record.extend(task_record(&archived.task));
+        writer.write_record(record)?;
+    }
+    crypto::write(&path, &writer.into_inner()?)?;
+    Ok(archive.len() - before)
+}
+
+/// Store the deleted tasks along with the time of deletion
+fn write_trash(trash: &[TrashedTask]) -> Result<()> {
+    let path = data_file(TRASH_NAME).ok_or_else(no_metadata)?;
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut header = vec!["deleted"];
+    header.extend(TASK_HEADER);
+    writer.write_record(header)?;
+    for trashed in trash {
+        let mut record = vec![trashed.deleted.to_string()];
+        record.extend(task_record(&trashed.task));
+        writer.write_record(record)?;
+    }
+    crypto::write(&path, &writer.into_inner()?)?;
+    Ok(())
+}
+
+/// Load the deleted tasks, a missing file means an empty trash
+fn read_trash() -> Result<Vec<TrashedTask>> {
+    let path = data_file(TRASH_NAME).ok_or_else(no_metadata)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut trash = Vec::new();
+    let data = crypto::read(&path)?;
+    let mut reader = ReaderBuilder::new().from_reader(&data[..]);
+    for record in reader.records() {
+        let record = record?;
+        trash.push(TrashedTask {
+            deleted: record[0].parse()?,
+            task: task_from_record(&record, 1)?,
+        });
+    }
+    Ok(trash)
+}
+
+/// Store the undo and redo stacks so that they survive across sessions
+///
+/// Every operation is a row tagged with its stack and step number, updates
+/// take two rows holding the task before and after the change
+fn write_history(history: &History) -> Result<()> {
+    let path = data_file(HISTORY_NAME).ok_or_else(no_metadata)?;
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut header = vec!["stack", "step", "kind", "index"];
+    header.extend(TASK_HEADER);
+    writer.write_record(header)?;
+
+    for (stack, steps) in [("undo", &history.undo), ("redo", &history.redo)] {
+        for (step_no, step) in steps.iter().enumerate() {
+            for op in step {
+                let rows = match op {
+                    Operation::Insert { index, task } => vec![("insert", *index, task)],
+                    Operation::Remove { index, task } => vec![("remove", *index, task)],
+                    Operation::Trash { index, task } => vec![("trash", *index, task)],
+                    Operation::Restore { index, task } => vec![("restore", *index, task)],
+                    Operation::Update { before, after } => {
+                        vec![("before", 0, before.as_ref()), ("after", 0, after.as_ref())]
+                    }
+                };
+                for (kind, index, task) in rows {
+                    let mut record = vec![
+                        stack.to_string(),
+                        step_no.to_string(),
+                        kind.to_string(),
+                        index.to_string(),
+                    ];
+                    record.extend(task_record(task));
+                    writer.write_record(record)?;
+                }
+            }
+        }
+    }
+    crypto::write(&path, &writer.into_inner()?)?;
+    Ok(())
+}
+
+/// Load the undo and redo stacks, a missing file means an empty history
+fn read_history() -> Result<History> {
+    let mut history = History::new();
+    let path = data_file(HISTORY_NAME).ok_or_else(no_metadata)?;
+    if !path.exists() {
+        return Ok(history);
+    }
+    let data = crypto::read(&path)?;
+    let mut reader = ReaderBuilder::new().from_reader(&data[..]);
+    let mut current: Option<(String, String)> = None;
+    let mut step: Step = Vec::new();
+    let mut before = None;
+    for record in reader.records() {
+        let record = record?;
+        let key = (record[0].to_string(), record[1].to_string());
+        if current.as_ref() != Some(&key) {
+            if let Some((stack, _)) = current.take() {
+                push_step(&mut history, &stack, std::mem::take(&mut step));
+            }
+            current = Some(key);
+        }
+        let index: usize = record[3].parse()?;
+        let task = task_from_record(&record, 4)?;
+        match &record[2] {
+            "insert" => step.push(Operation::Insert { index, task }),
+            "remove" => step.push(Operation::Remove { index, task }),
+            "trash" => step.push(Operation::Trash { index, task }),
+            "restore" => step.push(Operation::Restore { index, task }),
+            "before" => before = Some(task),
+            "after" => {
+                if let Some(before) = before.take() {
+                    step.push(Operation::update(before, task));
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some((stack, _)) = current {
+        push_step(&mut history, &stack, step);
+    }
+    Ok(history)
+}
+
+fn push_step(history: &mut History, stack: &str, step: Step) {
+    match stack {
+        "undo" => history.undo.push(step),
+        "redo" => history.redo.push(step),
+        _ => {}
+    }
+}
+
+/// Outcome of importing tasks from another format
+#[derive(Default)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+/// Find the existing task an imported task refers to
+///
+/// Tasks are matched by id, then by the `uid` kept from other tools. Tasks
+/// without any uid fall back to an identical description. A task already
+/// matched by an earlier task of the same import is never matched again
+fn find_existing<'a>(state: &'a State, task: &Task, matched: &HashSet<Id>) -> Option<&'a Task> {
+    let tasks: Vec<&Task> = state
+        .get_tasks()
+        .into_iter()
+        .filter(|existing| !matched.contains(&existing.id))
+        .collect();
+    if let Some(existing) = tasks.iter().find(|existing| existing.id == task.id) {
+        return Some(existing);
+    }
+    match task.extra.get("uid") {
+        Some(uid) => tasks
+            .into_iter()
+            .find(|existing| existing.extra.get("uid") == Some(uid)),
+        None => tasks
+            .into_iter()
+            .find(|existing| existing.desc == task.desc),
+    }
+}
+
+/// Read tasks in some format and merge them into the state as a single step
+///
+/// Imported tasks replace the existing tasks they match, others are added on top
+/// in the order they were read. Dependencies given as uids are resolved to the
+/// ids of imported or existing tasks, formats without dependencies keep the
+/// existing ones. The whole import can be reverted using undo.
+pub fn import_tasks(state: &mut State, format: &dyn Format, input: &str) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let mut new_ids: HashSet<Id> = HashSet::new();
+    let mut matched: HashSet<Id> = HashSet::new();
+
+    // give every task its final id
+    let mut imported: Vec<(Task, Option<Task>)> = Vec::new();
+    for mut task in format.parse(input)? {
+        let before = find_existing(state, &task, &matched).cloned();
+        if let Some(before) = &before {
+            matched.insert(before.id);
+            task.id = before.id;
+            task.created = task.created.or(before.created);
+            // most formats only keep whole seconds
+            for (date, old_date) in [
+                (&mut task.created, before.created),
+                (&mut task.completed_on, before.completed_on),
+                (&mut task.due, before.due),
+            ] {
+                if date.map(|date| date.trunc_subsecs(0))
+                    == old_date.map(|date| date.trunc_subsecs(0))
+                {
+                    *date = old_date;
+                }
+            }
+        } else {
+            task.id = loop {
+                let id = state.unused_id();
+                if new_ids.insert(id) {
+                    break id;
+                }
+            };
+        }
+        imported.push((task, before));
+    }
+
+    // resolve dependencies now that every task has an id
+    let uid_to_id: Vec<(String, Id)> = imported
+        .iter()
+        .map(|(task, _)| task)
+        .chain(state.get_tasks())
+        .filter_map(|task| Some((task.extra.get("uid")?.clone(), task.id)))
+        .collect();
+    for (task, before) in &mut imported {
+        if let Some(before) = before {
+            if task.depends.is_empty() && !task.extra.contains_key(DEPENDS_KEY) {
+                task.depends = before.depends.clone();
+            }
+        }
+        if let Some(depends) = task.extra.remove(DEPENDS_KEY) {
+            task.depends = depends
+                .split_whitespace()
+                .filter_map(|uid| {
+                    uid_to_id
+                        .iter()
+                        .find(|(other, _)| other == uid)
+                        .map(|(_, id)| *id)
+                })
+                .collect();
+        }
+    }
+
+    let mut step = Vec::new();
+    for (task, before) in imported {
+        match before {
+            Some(before) => {
+                let mut compare = task.clone();
+                compare.last_updated = before.last_updated;
+                if compare == before {
+                    summary.unchanged += 1;
+                } else {
+                    summary.updated += 1;
+                    step.push(Operation::update(before, task));
+                }
+            }
+            None => {
+                step.push(Operation::Insert {
+                    index: summary.added,
+                    task,
+                });
+                summary.added += 1;
+            }
+        }
+    }
+    state.apply_changes(step);
+    Ok(summary)
+}
+
+/// Write all the tasks in some format
+pub fn export_tasks(state: &State, format: &dyn Format) -> Result<String> {
+    format.render(&state.get_tasks())
+}
+
 /// Remove the directory which stores tasks data
 ///
+/// A snapshot of the tasks is taken first and the backups are left in place
+///
 /// User must confirm before this is being called
 pub fn remove_metadata() -> Result<()> {
-    match dirs::home_dir() {
-        Some(home) => {
-            fs::remove_dir_all(home.join(FOLDER_NAME))?;
+    match data_dir() {
+        Some(dir) => {
+            snapshot()?;
+            // the logs are shared, so the tasks are deleted there instead
+            if oplog::enabled() {
+                oplog::record(&oplog::load()?, &[], &[])?;
+            }
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    if ![BACKUP_FOLDER, OPS_FOLDER, THEMES_FOLDER]
+                        .iter()
+                        .any(|name| path.ends_with(name))
+                    {
+                        fs::remove_dir_all(path)?;
+                    }
+                } else if ![DEVICE_NAME, ENCRYPTION_NAME, CONFIG_NAME, KEYMAP_NAME]
+                    .iter()
+                    .any(|name| path.ends_with(name))
+                {
+                    fs::remove_file(path)?;
+                }
+            }
             Ok(())
         }
-        _ => Err(Box::new(Error::new(
-            ErrorKind::NotFound,
-            "No metadata found",
-        ))),
+        _ => Err(no_metadata()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_recovery() {
+        let task = |id, desc: &str| Task {
+            id,
+            desc: desc.to_string(),
+            ..Default::default()
+        };
+        let saved = State::from_tasks(vec![task(1, "pay the rent"), task(2, "water the plants")]);
+        let mut session = State::from_tasks(saved.get_tasks().into_iter().cloned().collect());
+        session.edit_task(1, "pay the rent and the bills");
+        session.remove_task(&2);
+        session.add_task("call home");
+        let descs = |state: &State| -> Vec<String> {
+            state
+                .get_tasks()
+                .iter()
+                .map(|task| task.desc.clone())
+                .collect()
+        };
+
+        // what write_recovery keeps and read_recovery gives back
+        let recovery = tasks_from_csv(&tasks_to_csv(session.get_tasks()).unwrap()).unwrap();
+        let mut state = State::from_tasks(saved.get_tasks().into_iter().cloned().collect());
+        let step = state.changes_to(&recovery);
+        assert_eq!(step.len(), 3);
+        state.apply_changes(step);
+        assert_eq!(descs(&state), descs(&session));
+        assert!(state.changes_to(&recovery).is_empty());
+
+        // the recovered changes are undone as one step
+        state.undo();
+        assert_eq!(descs(&state), descs(&saved));
+    }
+
+    #[test]
+    fn check_archive_reused_id() {
+        let task = Task {
+            id: 1234,
+            desc: "pay the rent".to_string(),
+            ..Default::default()
+        };
+        let mut archive = Vec::new();
+        add_to_archive(&mut archive, vec![task.clone()]);
+        // archived again, as when the data file of another device is merged
+        add_to_archive(&mut archive, vec![task.clone()]);
+        assert_eq!(archive.len(), 1);
+
+        // a different task which was given the same id
+        let reused = Task {
+            desc: "water the plants".to_string(),
+            ..task
+        };
+        add_to_archive(&mut archive, vec![reused]);
+        assert_eq!(archive.len(), 2);
+        assert_eq!(archive[1].task.desc, "water the plants");
+
+        let mut state = State::new();
+        state.reserved.insert(1234);
+        assert!((0..1000).all(|_| state.unused_id() != 1234));
+    }
+
+    #[test]
+    fn check_import_matches_once() {
+        let mut state = State::new();
+        state.add_task("buy milk");
+        let format = crate::formats::todotxt::TodoTxt;
+        let summary = import_tasks(&mut state, &format, "buy milk\nbuy milk\n").unwrap();
+        assert_eq!((summary.unchanged, summary.added), (1, 1));
+        assert_eq!(state.ids.len(), 2);
     }
 }
@@ -0,0 +1,306 @@
+use chrono::{Local, NaiveDate};
+use crossterm::event::{KeyCode, KeyEvent};
+
+use crate::{formats::local_date, input::Input, parse_age, state::Task};
+
+/// Format of due dates typed in the form
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Parse a priority, which is a letter from A to Z or nothing
+pub fn parse_priority(text: &str) -> std::result::Result<Option<char>, String> {
+    let text = text.trim();
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok(None),
+        (Some(ch), None) if ch.is_ascii_alphabetic() => Ok(Some(ch.to_ascii_uppercase())),
+        _ => Err(format!(
+            "priority must be a letter from A to Z, not `{text}`"
+        )),
+    }
+}
+
+/// Parse tags separated by commas or spaces, a leading `#` is dropped and so are repeated tags
+pub fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in text
+        .split([',', ' '])
+        .map(|tag| tag.trim().trim_start_matches('#'))
+        .filter(|tag| !tag.is_empty())
+    {
+        if !tags.iter().any(|other| other == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+
+/// A field set on several tasks at once from a prompt
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BatchField {
+    Tags,
+    Project,
+    Priority,
+}
+
+impl BatchField {
+    /// Text shown before the prompt
+    pub fn label(self) -> &'static str {
+        match self {
+            BatchField::Tags => "Tags (+add -remove)",
+            BatchField::Project => "Project",
+            BatchField::Priority => "Priority (A-Z)",
+        }
+    }
+
+    /// Set the field of a task from the text typed in the prompt
+    ///
+    /// Tags starting with `-` are removed and the others are added, an empty
+    /// project or priority clears it
+    pub fn apply(self, text: &str, task: &mut Task) -> std::result::Result<(), String> {
+        match self {
+            BatchField::Tags => {
+                for tag in parse_tags(text) {
+                    if let Some(tag) = tag.strip_prefix('-') {
+                        task.tags.retain(|other| other != tag);
+                    } else {
+                        let tag = tag.trim_start_matches('+');
+                        if !tag.is_empty() && !task.tags.iter().any(|other| other == tag) {
+                            task.tags.push(tag.to_string());
+                        }
+                    }
+                }
+            }
+            BatchField::Project => {
+                let project = text.trim();
+                task.project = (!project.is_empty()).then(|| project.to_string());
+            }
+            BatchField::Priority => task.priority = parse_priority(text)?,
+        }
+        Ok(())
+    }
+}
+
+/// Fields of the form, in the order Tab moves through them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Field {
+    Description,
+    Notes,
+    Priority,
+    Due,
+    Tags,
+}
+
+impl Field {
+    pub const ALL: [Field; 5] = [
+        Field::Description,
+        Field::Notes,
+        Field::Priority,
+        Field::Due,
+        Field::Tags,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::Description => "Description",
+            Field::Notes => "Notes",
+            Field::Priority => "Priority",
+            Field::Due => "Due",
+            Field::Tags => "Tags",
+        }
+    }
+
+    /// Hint shown while the field is empty
+    pub fn placeholder(self) -> &'static str {
+        match self {
+            Field::Description => "Enter your task details",
+            Field::Notes => "Longer details, links, ...",
+            Field::Priority => "A-Z",
+            Field::Due => "YYYY-MM-DD or 3d",
+            Field::Tags => "home, errand",
+        }
+    }
+}
+
+/// Fields of a task being added or edited in the TUI
+pub struct TaskForm {
+    pub description: Input,
+    pub notes: Input,
+    pub priority: Input,
+    pub due: Input,
+    pub tags: Input,
+    /// field receiving the keys
+    pub focus: Field,
+    /// why the task could not be saved
+    pub error: Option<String>,
+}
+
+impl Default for TaskForm {
+    fn default() -> Self {
+        Self {
+            description: Input::multiline(""),
+            notes: Input::multiline(""),
+            priority: Input::default(),
+            due: Input::default(),
+            tags: Input::default(),
+            focus: Field::Description,
+            error: None,
+        }
+    }
+}
+
+impl TaskForm {
+    /// Form filled in with the fields of a task
+    pub fn from_task(task: &Task) -> Self {
+        Self {
+            description: Input::multiline(&task.desc),
+            notes: Input::multiline(&task.notes),
+            priority: Input::new(&task.priority.map(String::from).unwrap_or_default()),
+            due: Input::new(
+                &task
+                    .due
+                    .map(|due| due.format(DATE_FORMAT).to_string())
+                    .unwrap_or_default(),
+            ),
+            tags: Input::new(&task.tags.join(", ")),
+            ..Default::default()
+        }
+    }
+
+    pub fn input(&self, field: Field) -> &Input {
+        match field {
+            Field::Description => &self.description,
+            Field::Notes => &self.notes,
+            Field::Priority => &self.priority,
+            Field::Due => &self.due,
+            Field::Tags => &self.tags,
+        }
+    }
+
+    pub fn input_mut(&mut self, field: Field) -> &mut Input {
+        match field {
+            Field::Description => &mut self.description,
+            Field::Notes => &mut self.notes,
+            Field::Priority => &mut self.priority,
+            Field::Due => &mut self.due,
+            Field::Tags => &mut self.tags,
+        }
+    }
+
+    /// The input of the focused field
+    pub fn focused(&mut self) -> &mut Input {
+        self.input_mut(self.focus)
+    }
+
+    /// Move the focus to the next or the previous field
+    pub fn cycle(&mut self, forward: bool) {
+        let idx = Field::ALL
+            .iter()
+            .position(|field| *field == self.focus)
+            .unwrap_or(0);
+        let len = Field::ALL.len();
+        self.focus = Field::ALL[if forward { idx + 1 } else { idx + len - 1 } % len];
+    }
+
+    /// Apply a key press which is not Enter or Esc
+    pub fn handle(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Tab => self.cycle(true),
+            KeyCode::BackTab => self.cycle(false),
+            _ => {
+                if !self.focused().handle(key) {
+                    // single line fields move between fields with Up and Down
+                    match key.code {
+                        KeyCode::Up => self.cycle(false),
+                        KeyCode::Down => self.cycle(true),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Write the fields into a task, the task is left alone if any is invalid
+    pub fn apply(&self, task: &mut Task) -> std::result::Result<(), String> {
+        let desc = self.description.text().trim();
+        if desc.is_empty() {
+            return Err("the description cannot be empty".to_string());
+        }
+        let priority = parse_priority(self.priority.text())?;
+        let unchanged = |text: &str| {
+            task.due
+                .filter(|due| due.format(DATE_FORMAT).to_string() == text)
+        };
+        let due = match self.due.text().trim() {
+            "" => None,
+            // the time of day is not shown, so it is kept unless the date is changed
+            text if unchanged(text).is_some() => unchanged(text),
+            text => Some(
+                NaiveDate::parse_from_str(text, DATE_FORMAT)
+                    .ok()
+                    .and_then(local_date)
+                    .or_else(|| Some(Local::now() + parse_age(text).ok()?))
+                    .ok_or_else(|| format!("due must be like 2024-01-31 or 3d, not `{text}`"))?,
+            ),
+        };
+        let tags = parse_tags(self.tags.text());
+
+        task.desc = desc.to_string();
+        task.notes = self.notes.text().trim_end().to_string();
+        task.priority = priority;
+        task.due = due;
+        task.tags = tags;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_apply() {
+        let mut task = Task {
+            desc: "old".to_string(),
+            tags: vec!["home".to_string()],
+            ..Default::default()
+        };
+        let mut form = TaskForm::from_task(&task);
+        assert_eq!(form.tags.text(), "home");
+        form.description = Input::multiline("buy milk\nand eggs");
+        form.priority = Input::new("b");
+        form.due = Input::new("2024-01-31");
+        form.tags = Input::new("#home, errand home");
+        form.apply(&mut task).unwrap();
+        assert_eq!(task.desc, "buy milk\nand eggs");
+        assert_eq!(task.priority, Some('B'));
+        assert_eq!(
+            task.due.unwrap().format(DATE_FORMAT).to_string(),
+            "2024-01-31"
+        );
+        assert_eq!(task.tags, ["home", "errand"]);
+
+        form.priority = Input::new("AB");
+        assert!(form.apply(&mut task).is_err());
+        assert_eq!(task.priority, Some('B'));
+        form.priority = Input::default();
+        form.due = Input::new("soon");
+        assert!(form.apply(&mut task).is_err());
+
+        // the time of an unchanged due date is kept
+        let due = Local::now();
+        task.due = Some(due);
+        TaskForm::from_task(&task).apply(&mut task).unwrap();
+        assert_eq!(task.due, Some(due));
+
+        BatchField::Tags.apply("+work, -home", &mut task).unwrap();
+        assert_eq!(task.tags, ["errand", "work"]);
+        BatchField::Project.apply(" ", &mut task).unwrap();
+        assert_eq!(task.project, None);
+        assert!(BatchField::Priority.apply("high", &mut task).is_err());
+
+        form.cycle(false);
+        assert_eq!(form.focus, Field::Tags);
+        form.cycle(true);
+        assert_eq!(form.focus, Field::Description);
+    }
+}
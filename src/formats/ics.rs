@@ -0,0 +1,352 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+use super::{local_date, Format};
+use crate::{state::Task, Result};
+
+/// Tasks stored as `VTODO` components of an iCalendar file (RFC 5545)
+///
+/// The `UID` of tasks created here is `<id>@todo-cli`, any other `UID` is
+/// kept in `Task::extra` so that importing again updates the same tasks.
+/// `RRULE`, properties which are not modelled and nested components like
+/// alarms are kept in `Task::extra` as well and written back on export.
+pub struct Ics;
+
+const UID_SUFFIX: &str = "@todo-cli";
+/// Key in `Task::extra` holding the lines which are not modelled, in the order
+/// they were read
+const UNKNOWN_KEY: &str = "ics:unknown";
+/// Key in `Task::extra` set when the due date is a whole day without a time
+const DUE_DATE_KEY: &str = "ics:due-date";
+const DATE_FORMAT: &str = "%Y%m%d";
+const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// A single content line, `NAME;PARAMS:VALUE`
+struct Property<'a> {
+    name: String,
+    params: &'a str,
+    value: &'a str,
+}
+
+impl<'a> Property<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let mut quoted = false;
+        let colon = line.char_indices().find_map(|(idx, ch)| match ch {
+            '"' => {
+                quoted = !quoted;
+                None
+            }
+            ':' if !quoted => Some(idx),
+            _ => None,
+        })?;
+        let (head, value) = (&line[..colon], &line[colon + 1..]);
+        let (name, params) = head.split_once(';').unwrap_or((head, ""));
+        Some(Self {
+            name: name.to_ascii_uppercase(),
+            params,
+            value,
+        })
+    }
+}
+
+/// Join the lines which were folded to keep them short
+fn unfold(input: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in input.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Split a line into lines of at most 75 octets
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut len = 0;
+    for ch in line.chars() {
+        if len + ch.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+        folded.push(ch);
+        len += ch.len_utf8();
+    }
+    folded + "\r\n"
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(other) => unescaped.push(other),
+                None => {}
+            }
+        } else {
+            unescaped.push(ch);
+        }
+    }
+    unescaped
+}
+
+/// Split a list of values on commas which are not escaped
+fn split_list(text: &str) -> Vec<String> {
+    let mut values = vec![String::new()];
+    let mut escaped = false;
+    for ch in text.chars() {
+        match ch {
+            ',' if !escaped => values.push(String::new()),
+            _ => values.last_mut().unwrap().push(ch),
+        }
+        escaped = ch == '\\' && !escaped;
+    }
+    values
+        .iter()
+        .map(|value| unescape(value.trim()))
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Parse a `DATE` or `DATE-TIME` value
+///
+/// Times in UTC are converted to local time, floating times and times with a
+/// `TZID` are taken as local time
+pub(super) fn parse_date(value: &str) -> Option<DateTime<Local>> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        let date = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some(Utc.from_utc_datetime(&date).with_timezone(&Local));
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        return Local.from_local_datetime(&date).earliest();
+    }
+    local_date(NaiveDate::parse_from_str(value, DATE_FORMAT).ok()?)
+}
+
+pub(super) fn format_date(date: DateTime<Local>) -> String {
+    date.with_timezone(&Utc)
+        .format(DATE_TIME_FORMAT)
+        .to_string()
+}
+
+fn parse_todo(lines: &[String]) -> Task {
+    let mut task = Task {
+        last_updated: Local::now(),
+        ..Default::default()
+    };
+    let mut nested = 0;
+    let mut unknown = Vec::new();
+    for line in lines {
+        let Some(property) = Property::parse(line) else {
+            continue;
+        };
+        // nested components like alarms are not modelled
+        match (property.name.as_str(), property.value) {
+            ("BEGIN", _) => {
+                nested += 1;
+                unknown.push(line.as_str());
+            }
+            ("END", _) => {
+                nested -= 1;
+                unknown.push(line.as_str());
+            }
+            _ if nested > 0 => unknown.push(line.as_str()),
+            ("UID", uid) => match uid.strip_suffix(UID_SUFFIX).map(str::parse) {
+                Some(Ok(id)) => task.id = id,
+                _ => {
+                    task.extra.insert("uid".to_string(), uid.to_string());
+                }
+            },
+            ("SUMMARY", summary) => task.desc = unescape(summary),
+            ("DESCRIPTION", description) => task.notes = unescape(description),
+            ("STATUS", "COMPLETED") => task.completed = true,
+            ("STATUS", "NEEDS-ACTION") => {}
+            ("STATUS", status) => {
+                task.extra.insert("status".to_string(), status.to_string());
+            }
+            ("PRIORITY", priority) => {
+                task.priority = match priority.trim().parse::<u8>() {
+                    Ok(priority @ 1..=9) => Some((b'A' + priority - 1) as char),
+                    _ => None,
+                }
+            }
+            ("CATEGORIES", categories) => task.tags.extend(split_list(categories)),
+            ("X-TODO-CLI-PROJECT", project) => task.project = Some(unescape(project)),
+            ("DUE", due) => {
+                task.due = parse_date(due);
+                if property.params.to_ascii_uppercase().contains("VALUE=DATE") && due.len() == 8 {
+                    task.extra.insert(DUE_DATE_KEY.to_string(), "1".to_string());
+                }
+            }
+            ("COMPLETED", completed) => task.completed_on = parse_date(completed),
+            ("CREATED", created) => task.created = parse_date(created),
+            ("LAST-MODIFIED", modified) => {
+                if let Some(modified) = parse_date(modified) {
+                    task.last_updated = modified;
+                }
+            }
+            ("DTSTAMP", _) => {}
+            ("RRULE", rrule) => {
+                task.extra.insert("rrule".to_string(), rrule.to_string());
+            }
+            _ => unknown.push(line.as_str()),
+        }
+    }
+    if !unknown.is_empty() {
+        task.extra
+            .insert(UNKNOWN_KEY.to_string(), unknown.join("\n"));
+    }
+    task
+}
+
+fn render_todo(task: &Task) -> String {
+    let mut lines = vec!["BEGIN:VTODO".to_string()];
+    let uid = match task.extra.get("uid") {
+        Some(uid) => uid.clone(),
+        None => format!("{}{UID_SUFFIX}", task.id),
+    };
+    lines.push(format!("UID:{uid}"));
+    lines.push(format!("DTSTAMP:{}", format_date(Local::now())));
+    lines.push(format!("SUMMARY:{}", escape(&task.desc)));
+    if !task.notes.is_empty() {
+        lines.push(format!("DESCRIPTION:{}", escape(&task.notes)));
+    }
+    let status = match (task.completed, task.extra.get("status")) {
+        (true, _) => "COMPLETED",
+        (false, Some(status)) => status,
+        (false, None) => "NEEDS-ACTION",
+    };
+    lines.push(format!("STATUS:{status}"));
+    if let Some(priority @ 'A'..='I') = task.priority {
+        lines.push(format!("PRIORITY:{}", priority as u8 - b'A' + 1));
+    }
+    if !task.tags.is_empty() {
+        let tags: Vec<String> = task.tags.iter().map(|tag| escape(tag)).collect();
+        lines.push(format!("CATEGORIES:{}", tags.join(",")));
+    }
+    if let Some(project) = &task.project {
+        lines.push(format!("X-TODO-CLI-PROJECT:{}", escape(project)));
+    }
+    match task.due {
+        // a whole day stays one, unless a time was given to it since
+        Some(due) if task.extra.contains_key(DUE_DATE_KEY) && due.time() == NaiveTime::MIN => {
+            lines.push(format!("DUE;VALUE=DATE:{}", due.format(DATE_FORMAT)));
+        }
+        Some(due) => lines.push(format!("DUE:{}", format_date(due))),
+        None => {}
+    }
+    if let Some(created) = task.created {
+        lines.push(format!("CREATED:{}", format_date(created)));
+    }
+    if task.completed {
+        let completed = task.completed_on.unwrap_or(task.last_updated);
+        lines.push(format!("COMPLETED:{}", format_date(completed)));
+    }
+    lines.push(format!("LAST-MODIFIED:{}", format_date(task.last_updated)));
+    if let Some(rrule) = task.extra.get("rrule") {
+        lines.push(format!("RRULE:{rrule}"));
+    }
+    if let Some(unknown) = task.extra.get(UNKNOWN_KEY) {
+        lines.extend(unknown.lines().map(str::to_string));
+    }
+    lines.push("END:VTODO".to_string());
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+impl Format for Ics {
+    fn parse(&self, input: &str) -> Result<Vec<Task>> {
+        let mut tasks = Vec::new();
+        let mut todo: Option<Vec<String>> = None;
+        for line in unfold(input) {
+            let line = line.trim_end_matches('\r');
+            match (line.to_ascii_uppercase().as_str(), &mut todo) {
+                ("BEGIN:VTODO", None) => todo = Some(Vec::new()),
+                ("END:VTODO", Some(lines)) => {
+                    tasks.push(parse_todo(lines));
+                    todo = None;
+                }
+                (_, Some(lines)) => lines.push(line.to_string()),
+                _ => {}
+            }
+        }
+        Ok(tasks)
+    }
+
+    fn render(&self, tasks: &[&Task]) -> Result<String> {
+        let mut output = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n");
+        output.push_str("PRODID:-//todo-cli//EN\r\n");
+        for task in tasks {
+            output.push_str(&render_todo(task));
+        }
+        output.push_str("END:VCALENDAR\r\n");
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VTODO\r
+UID:abc-123\r
+SUMMARY:Buy milk\\, eggs\r
+DESCRIPTION:From the shop\\non the corner\r
+STATUS:COMPLETED\r
+PRIORITY:2\r
+CATEGORIES:home,errands\r
+DUE;VALUE=DATE:20240105\r
+COMPLETED:20240104T101500Z\r
+RRULE:FREQ=WEEKLY\r
+X-CUSTOM;LANG=en:keep\r
+ me\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+END:VALARM\r
+X-CUSTOM:again\r
+END:VTODO\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn check_parse() {
+        let tasks = Ics.parse(CALENDAR).unwrap();
+        assert_eq!(tasks.len(), 1);
+        let task = &tasks[0];
+        assert_eq!(task.desc, "Buy milk, eggs");
+        assert_eq!(task.notes, "From the shop\non the corner");
+        assert!(task.completed);
+        assert_eq!(task.priority, Some('B'));
+        assert_eq!(task.tags, ["home", "errands"]);
+        assert_eq!(task.due, parse_date("20240105"));
+        assert_eq!(task.completed_on, parse_date("20240104T101500Z"));
+        assert_eq!(task.extra["uid"], "abc-123");
+        assert_eq!(task.extra["rrule"], "FREQ=WEEKLY");
+        assert_eq!(
+            task.extra[UNKNOWN_KEY],
+            "X-CUSTOM;LANG=en:keepme\nBEGIN:VALARM\nACTION:DISPLAY\nEND:VALARM\nX-CUSTOM:again"
+        );
+    }
+
+    #[test]
+    fn check_round_trip() {
+        let tasks = Ics.parse(CALENDAR).unwrap();
+        let rendered = Ics.render(&tasks.iter().collect::<Vec<_>>()).unwrap();
+        let again = Ics.parse(&rendered).unwrap();
+        let mut expected = tasks[0].clone();
+        expected.last_updated = again[0].last_updated;
+        assert!(again[0] == expected);
+        assert!(rendered.contains("\r\nDUE;VALUE=DATE:20240105\r\n"));
+        assert!(rendered.lines().all(|line| line.len() <= 76));
+    }
+}
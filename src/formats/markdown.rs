@@ -0,0 +1,292 @@
+use chrono::{DateTime, Local};
+
+use super::Format;
+use crate::{
+    history::{Operation, Step},
+    state::{State, Task},
+    Id, Result,
+};
+
+/// Tasks stored as a Markdown checklist, `- [ ] item` and `- [x] item`
+///
+/// Tasks are grouped under a `## project` heading and tags are written as
+/// `#tag`. The id of each task is kept in an html comment at the end of the
+/// line so that edits on either side can be matched up.
+pub struct Markdown;
+
+const ID_PREFIX: &str = "<!-- todo-cli:";
+const ID_SUFFIX: &str = "-->";
+/// Level of the headings written for projects, other headings end a project
+const PROJECT_LEVEL: usize = 2;
+
+/// A single line of a checklist
+struct ChecklistItem<'a> {
+    /// indentation and bullet before the checkbox
+    prefix: &'a str,
+    checked: bool,
+    /// the text after the checkbox without the id comment
+    text: &'a str,
+    id: Option<Id>,
+}
+
+impl<'a> ChecklistItem<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        let mut chars = trimmed.chars();
+        let bullet = chars.next()?;
+        if !matches!(bullet, '-' | '*' | '+') {
+            return None;
+        }
+        let rest = chars.as_str().strip_prefix(' ')?;
+        let checked = match rest.get(..3)? {
+            "[ ]" => false,
+            "[x]" | "[X]" => true,
+            _ => return None,
+        };
+        let text = rest[3..].trim();
+        let (text, id) = match text.rfind(ID_PREFIX) {
+            Some(start) => {
+                let id = text[start + ID_PREFIX.len()..]
+                    .trim_end()
+                    .strip_suffix(ID_SUFFIX)
+                    .and_then(|id| id.trim().parse().ok());
+                (text[..start].trim_end(), id)
+            }
+            None => (text, None),
+        };
+        Some(Self {
+            prefix: &line[..indent + 2],
+            checked,
+            text,
+            id,
+        })
+    }
+
+    fn render(&self, id: Id) -> String {
+        format!(
+            "{}[{}] {} {ID_PREFIX}{id} {ID_SUFFIX}",
+            self.prefix,
+            if self.checked { 'x' } else { ' ' },
+            self.text
+        )
+    }
+}
+
+/// Parse a `#` heading along with its level
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    let heading = line.trim_start_matches('#');
+    if heading.len() == line.len() || !heading.starts_with(' ') {
+        return None;
+    }
+    Some((line.len() - heading.len(), heading.trim()))
+}
+
+fn is_tag(word: &str) -> bool {
+    word.strip_prefix('#')
+        .and_then(|tag| tag.chars().next())
+        .is_some_and(char::is_alphabetic)
+}
+
+/// Turn the text of an item into a task
+fn task_from_item(item: &ChecklistItem, project: Option<&str>) -> Task {
+    let mut task = Task {
+        id: item.id.unwrap_or_default(),
+        last_updated: Local::now(),
+        project: project.map(String::from),
+        ..Default::default()
+    };
+    let mut words = Vec::new();
+    for word in item.text.split_whitespace() {
+        if is_tag(word) {
+            task.tags.push(word[1..].to_string());
+        } else {
+            words.push(word);
+        }
+    }
+    task.desc = words.join(" ");
+    if item.checked {
+        task.mark_complete();
+    }
+    task
+}
+
+fn render_item(task: &Task) -> String {
+    let mut text = task.desc.clone();
+    for tag in &task.tags {
+        text.push_str(&format!(" #{}", tag.split_whitespace().collect::<String>()));
+    }
+    ChecklistItem {
+        prefix: "- ",
+        checked: task.completed,
+        text: &text,
+        id: Some(task.id),
+    }
+    .render(task.id)
+}
+
+impl Format for Markdown {
+    fn parse(&self, input: &str) -> Result<Vec<Task>> {
+        let mut project = None;
+        let mut tasks = Vec::new();
+        for line in input.lines() {
+            if let Some((level, heading)) = parse_heading(line) {
+                project = (level == PROJECT_LEVEL).then_some(heading);
+            } else if let Some(item) = ChecklistItem::parse(line) {
+                tasks.push(task_from_item(&item, project));
+            }
+        }
+        Ok(tasks)
+    }
+
+    fn render(&self, tasks: &[&Task]) -> Result<String> {
+        let mut projects: Vec<Option<&String>> = Vec::new();
+        for task in tasks {
+            if !projects.contains(&task.project.as_ref()) {
+                projects.push(task.project.as_ref());
+            }
+        }
+        projects.sort();
+
+        let mut output = String::new();
+        for project in projects {
+            if let Some(project) = project {
+                output.push_str(&format!("\n## {project}\n\n"));
+            }
+            for task in tasks.iter().filter(|task| task.project.as_ref() == project) {
+                output.push_str(&render_item(task));
+                output.push('\n');
+            }
+        }
+        Ok(output.trim_start().to_string())
+    }
+}
+
+/// Outcome of synchronizing a notes file
+#[derive(Default)]
+pub struct SyncSummary {
+    /// tasks created from new checklist items
+    pub added: usize,
+    /// tasks whose status was taken from the file
+    pub from_file: usize,
+    /// checklist items whose status was taken from the tasks
+    pub to_file: usize,
+}
+
+/// Reconcile the completion state of the checklist in a notes file with the tasks
+///
+/// When an item and its task disagree, the side changed last wins, the file
+/// being considered changed at `modified`. Items without an id become new
+/// tasks and get their id appended. Every other line is kept as it is.
+///
+/// Returns the new contents of the file along with the changes made to tasks
+pub fn sync(state: &State, input: &str, modified: DateTime<Local>) -> (String, Step, SyncSummary) {
+    let mut summary = SyncSummary::default();
+    let mut step = Vec::new();
+    let mut lines = Vec::new();
+    let mut project = None;
+    let mut new_ids = Vec::new();
+    for line in input.lines() {
+        if let Some((level, heading)) = parse_heading(line) {
+            project = (level == PROJECT_LEVEL).then_some(heading);
+        }
+        let Some(mut item) = ChecklistItem::parse(line) else {
+            lines.push(line.to_string());
+            continue;
+        };
+        match item.id.and_then(|id| state.tasks.get(&id)) {
+            Some(list_item) if list_item.task.completed != item.checked => {
+                let before = &list_item.task;
+                if before.last_updated > modified {
+                    item.checked = before.completed;
+                    summary.to_file += 1;
+                    lines.push(item.render(before.id));
+                } else {
+                    let mut after = before.clone();
+                    if item.checked {
+                        after.mark_complete();
+                    } else {
+                        after.mark_incomplete();
+                    }
+                    after.last_updated = Local::now();
+                    step.push(Operation::update(before.clone(), after));
+                    summary.from_file += 1;
+                    lines.push(line.to_string());
+                }
+            }
+            Some(_) => lines.push(line.to_string()),
+            // items of tasks which were removed are left alone
+            None if item.id.is_some() => lines.push(line.to_string()),
+            None => {
+                let mut task = task_from_item(&item, project);
+                task.id = loop {
+                    let id = state.unused_id();
+                    if !new_ids.contains(&id) {
+                        break id;
+                    }
+                };
+                new_ids.push(task.id);
+                lines.push(item.render(task.id));
+                step.push(Operation::Insert {
+                    index: summary.added,
+                    task,
+                });
+                summary.added += 1;
+            }
+        }
+    }
+    let mut output = lines.join("\n");
+    if input.ends_with('\n') {
+        output.push('\n');
+    }
+    (output, step, summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn check_round_trip() {
+        let input = "- [ ] Write report #work <!-- todo-cli:1234 -->\n\n## home\n\n- [x] Buy milk <!-- todo-cli:4321 -->\n";
+        let tasks = Markdown.parse(input).unwrap();
+        assert_eq!(tasks[0].id, 1234);
+        assert_eq!(tasks[0].tags, ["work"]);
+        assert_eq!(tasks[1].project.as_deref(), Some("home"));
+        assert!(tasks[1].completed);
+        let rendered = Markdown.render(&tasks.iter().collect::<Vec<_>>()).unwrap();
+        assert_eq!(rendered, input);
+    }
+
+    #[test]
+    fn check_sync() {
+        let mut state = State::new();
+        state.add_task("old");
+        state.add_task("new");
+        let (new_id, old_id) = (state.ids[0], state.ids[1]);
+        let input = format!(
+            "# Notes\n  * [x] old <!-- todo-cli:{old_id} -->\n- [x] new <!-- todo-cli:{new_id} -->\n- [ ] added\ntext\n"
+        );
+
+        // the file was changed after old but before new
+        let old_time = Local::now() - Duration::hours(2);
+        state.tasks.get_mut(&old_id).unwrap().task.last_updated = old_time;
+        let (output, step, summary) = sync(&state, &input, old_time + Duration::hours(1));
+        assert_eq!(
+            (summary.added, summary.from_file, summary.to_file),
+            (1, 1, 1)
+        );
+
+        state.apply_changes(step);
+        assert!(state.tasks.get(&old_id).unwrap().task.completed);
+        let added = state.ids[0];
+        // only the headings written on export are projects
+        assert_eq!(state.tasks[&added].task.project, None);
+        let expected = format!(
+            "# Notes\n  * [x] old <!-- todo-cli:{old_id} -->\n- [ ] new <!-- todo-cli:{new_id} -->\n- [ ] added <!-- todo-cli:{added} -->\ntext\n"
+        );
+        assert_eq!(output, expected);
+    }
+}
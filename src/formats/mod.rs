@@ -0,0 +1,53 @@
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+
+use crate::{state::Task, Result};
+
+/// iCalendar `VTODO` components
+pub mod ics;
+/// Markdown checklists
+pub mod markdown;
+/// Taskwarrior JSON export
+pub mod taskwarrior;
+/// The todo.txt format, one task per line
+pub mod todotxt;
+
+/// Key in `Task::extra` where a format can keep the space separated uids of
+/// the tasks a task depends on, they are turned into ids while importing
+pub const DEPENDS_KEY: &str = "depends";
+
+/// A file format which tasks can be imported from and exported to
+pub trait Format {
+    /// Read the tasks stored in the given text
+    ///
+    /// Tasks which do not know about an existing id are given id 0
+    fn parse(&self, input: &str) -> Result<Vec<Task>>;
+    /// Write the tasks in this format
+    fn render(&self, tasks: &[&Task]) -> Result<String>;
+}
+
+/// All the supported formats
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum FormatKind {
+    Todotxt,
+    Ics,
+    Markdown,
+    Taskwarrior,
+}
+
+impl FormatKind {
+    pub fn format(&self) -> Box<dyn Format> {
+        match self {
+            Self::Todotxt => Box::new(todotxt::TodoTxt),
+            Self::Ics => Box::new(ics::Ics),
+            Self::Markdown => Box::new(markdown::Markdown),
+            Self::Taskwarrior => Box::new(taskwarrior::Taskwarrior),
+        }
+    }
+}
+
+/// Midnight of the given day in local time
+pub(crate) fn local_date(date: NaiveDate) -> Option<DateTime<Local>> {
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+}
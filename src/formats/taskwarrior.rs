@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+
+use chrono::Local;
+use serde_json::Value;
+
+use super::{
+    ics::{format_date, parse_date},
+    Format, DEPENDS_KEY,
+};
+use crate::{state::Task, Id, Result};
+
+/// Tasks in the JSON format of `task export` and `task import` from Taskwarrior
+///
+/// The uuid is kept in `Task::extra` as the uid. Tasks created here, or whose
+/// uid comes from another format and is not a uuid, get a uuid derived from
+/// their id. Statuses other than pending and completed as well as
+/// attributes and UDAs which are not modelled are kept in `Task::extra`, so
+/// they round-trip.
+pub struct Taskwarrior;
+
+/// Prefix of the keys in `Task::extra` holding unknown attributes as json
+const UDA_PREFIX: &str = "tw:";
+/// Key in `Task::extra` holding a status which is not modelled
+const STATUS_KEY: &str = "tw:status";
+/// Key in `Task::extra` holding the space separated times the annotations,
+/// one per line of the notes, were made
+const ENTRIES_KEY: &str = "tw:annotation-entries";
+/// Prefix of the uuids generated from the id of a task
+const UUID_PREFIX: &str = "00000000-0000-4000-8000-";
+
+/// Attributes computed by Taskwarrior which are not worth keeping
+const COMPUTED: [&str; 2] = ["id", "urgency"];
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Annotation {
+    entry: String,
+    description: String,
+}
+
+/// A task as written by Taskwarrior
+///
+/// This is not the serde derive of `Task` with renamed fields: several
+/// attributes depend on more than one field of a task or on the other tasks.
+/// The uuid comes from the id or the kept uid, the status from the
+/// completion and a kept status, the annotations from the notes and the kept
+/// entry times, and depends from the uuids of the other tasks. Field level
+/// `with` adapters only see their own field.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TwTask {
+    uuid: String,
+    description: String,
+    status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
+    /// a list of uuids, or a comma separated string in older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    depends: Option<Value>,
+    /// user defined attributes and anything else
+    #[serde(flatten)]
+    udas: BTreeMap<String, Value>,
+}
+
+/// Whether a uid has the `8-4-4-4-12` hexadecimal form of a uuid
+fn is_uuid(uid: &str) -> bool {
+    let groups: Vec<&str> = uid.split('-').collect();
+    groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+        && groups
+            .iter()
+            .all(|group| group.chars().all(|ch| ch.is_ascii_hexdigit()))
+}
+
+fn uuid_of(task: &Task) -> String {
+    match task.extra.get("uid") {
+        Some(uid) if is_uuid(uid) => uid.clone(),
+        _ => format!("{UUID_PREFIX}{:012}", task.id),
+    }
+}
+
+fn into_task(tw: TwTask) -> Task {
+    let entries: Vec<String> = tw
+        .annotations
+        .iter()
+        .map(|annotation| annotation.entry.clone())
+        .collect();
+    let mut task = Task {
+        desc: tw.description,
+        last_updated: tw
+            .modified
+            .as_deref()
+            .and_then(parse_date)
+            .unwrap_or_else(Local::now),
+        created: tw.entry.as_deref().and_then(parse_date),
+        completed_on: tw.end.as_deref().and_then(parse_date),
+        due: tw.due.as_deref().and_then(parse_date),
+        priority: match tw.priority.as_deref() {
+            Some("H") => Some('A'),
+            Some("M") => Some('B'),
+            Some("L") => Some('C'),
+            _ => None,
+        },
+        tags: tw.tags,
+        project: tw.project,
+        notes: tw
+            .annotations
+            .into_iter()
+            .map(|annotation| annotation.description)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ..Default::default()
+    };
+    match tw.uuid.strip_prefix(UUID_PREFIX).map(str::parse::<Id>) {
+        Some(Ok(id)) => task.id = id,
+        _ => {
+            task.extra.insert("uid".to_string(), tw.uuid);
+        }
+    }
+    match tw.status.as_str() {
+        "completed" => task.completed = true,
+        "pending" => {}
+        status => {
+            task.extra
+                .insert(STATUS_KEY.to_string(), status.to_string());
+        }
+    }
+    let depends: Vec<String> = match tw.depends {
+        Some(Value::Array(uuids)) => uuids
+            .iter()
+            .filter_map(|uuid| uuid.as_str().map(String::from))
+            .collect(),
+        Some(Value::String(uuids)) => uuids.split(',').map(String::from).collect(),
+        _ => Vec::new(),
+    };
+    if !depends.is_empty() {
+        task.extra
+            .insert(DEPENDS_KEY.to_string(), depends.join(" "));
+    }
+    if !entries.is_empty() {
+        task.extra
+            .insert(ENTRIES_KEY.to_string(), entries.join(" "));
+    }
+    for (key, value) in tw.udas {
+        if !COMPUTED.contains(&key.as_str()) {
+            task.extra
+                .insert(format!("{UDA_PREFIX}{key}"), value.to_string());
+        }
+    }
+    task
+}
+
+fn from_task(task: &Task, tasks: &[&Task]) -> TwTask {
+    let status = match (task.completed, task.extra.get(STATUS_KEY)) {
+        (true, _) => "completed".to_string(),
+        (false, Some(status)) => status.clone(),
+        (false, None) => "pending".to_string(),
+    };
+    let depends: Vec<Value> = task
+        .depends
+        .iter()
+        .filter_map(|id| tasks.iter().find(|other| other.id == *id))
+        .map(|other| Value::String(uuid_of(other)))
+        .collect();
+    let udas = task
+        .extra
+        .iter()
+        .filter(|(key, _)| ![STATUS_KEY, ENTRIES_KEY].contains(&key.as_str()))
+        .filter_map(|(key, value)| {
+            let key = key.strip_prefix(UDA_PREFIX)?;
+            Some((key.to_string(), serde_json::from_str(value).ok()?))
+        })
+        .collect();
+    let entries: Vec<&str> = task
+        .extra
+        .get(ENTRIES_KEY)
+        .map(|entries| entries.split_whitespace().collect())
+        .unwrap_or_default();
+    TwTask {
+        uuid: uuid_of(task),
+        description: task.desc.clone(),
+        status,
+        entry: Some(format_date(task.created.unwrap_or(task.last_updated))),
+        modified: Some(format_date(task.last_updated)),
+        end: task
+            .completed
+            .then(|| format_date(task.completed_on.unwrap_or(task.last_updated))),
+        due: task.due.map(format_date),
+        priority: match task.priority {
+            Some('A') => Some("H".to_string()),
+            Some('B') => Some("M".to_string()),
+            Some(_) => Some("L".to_string()),
+            None => None,
+        },
+        tags: task.tags.clone(),
+        project: task.project.clone(),
+        annotations: task
+            .notes
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(idx, line)| Annotation {
+                entry: entries
+                    .get(idx)
+                    .map(|entry| entry.to_string())
+                    .unwrap_or_else(|| format_date(task.last_updated)),
+                description: line.to_string(),
+            })
+            .collect(),
+        depends: (!depends.is_empty()).then_some(Value::Array(depends)),
+        udas,
+    }
+}
+
+impl Format for Taskwarrior {
+    fn parse(&self, input: &str) -> Result<Vec<Task>> {
+        // an array from `task export`, or one task per line from older versions
+        let tasks: Vec<TwTask> = match serde_json::from_str(input) {
+            Ok(tasks) => tasks,
+            Err(_) => input
+                .lines()
+                .map(|line| line.trim().trim_end_matches(','))
+                .filter(|line| !line.is_empty() && *line != "[" && *line != "]")
+                .map(serde_json::from_str)
+                .collect::<std::result::Result<_, _>>()?,
+        };
+        Ok(tasks.into_iter().map(into_task).collect())
+    }
+
+    fn render(&self, tasks: &[&Task]) -> Result<String> {
+        let tw_tasks: Vec<TwTask> = tasks.iter().map(|task| from_task(task, tasks)).collect();
+        Ok(serde_json::to_string_pretty(&tw_tasks)? + "\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"[
+{"id":1,"description":"Write docs","entry":"20240101T100000Z","modified":"20240102T100000Z","project":"cli","status":"pending","tags":["docs"],"uuid":"a1b2c3d4-0000-4000-8000-00000000a1b2","priority":"H","depends":["c3d4e5f6-0000-4000-8000-00000000c3d4"],"urgency":5.2,"estimate":3,"annotations":[{"entry":"20240101T110000Z","description":"see wiki"}]},
+{"id":0,"description":"Release","end":"20240103T100000Z","entry":"20240101T100000Z","modified":"20240103T100000Z","status":"completed","uuid":"c3d4e5f6-0000-4000-8000-00000000c3d4"}
+]"#;
+
+    #[test]
+    fn check_parse() {
+        let tasks = Taskwarrior.parse(EXPORT).unwrap();
+        let task = &tasks[0];
+        assert_eq!(task.desc, "Write docs");
+        assert_eq!(task.priority, Some('A'));
+        assert_eq!(task.project.as_deref(), Some("cli"));
+        assert_eq!(task.notes, "see wiki");
+        assert_eq!(task.extra["uid"], "a1b2c3d4-0000-4000-8000-00000000a1b2");
+        assert_eq!(
+            task.extra[DEPENDS_KEY],
+            "c3d4e5f6-0000-4000-8000-00000000c3d4"
+        );
+        assert_eq!(task.extra[ENTRIES_KEY], "20240101T110000Z");
+        assert_eq!(task.extra["tw:estimate"], "3");
+        assert!(!task.extra.contains_key("tw:urgency"));
+        assert!(!tasks[1].extra.contains_key(DEPENDS_KEY));
+        assert!(tasks[1].completed);
+        assert_eq!(tasks[1].completed_on, parse_date("20240103T100000Z"));
+    }
+
+    #[test]
+    fn check_round_trip() {
+        let mut tasks = Taskwarrior.parse(EXPORT).unwrap();
+        tasks[0].id = 1;
+        tasks[1].id = 2;
+        tasks[0].depends = vec![2];
+        tasks[0].extra.remove(DEPENDS_KEY);
+        // uids from other formats are not uuids
+        tasks[1]
+            .extra
+            .insert("uid".to_string(), "abc-123".to_string());
+        let rendered = Taskwarrior
+            .render(&tasks.iter().collect::<Vec<_>>())
+            .unwrap();
+        let json: Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(json[0]["uuid"], "a1b2c3d4-0000-4000-8000-00000000a1b2");
+        assert_eq!(
+            json[0]["depends"][0],
+            "00000000-0000-4000-8000-000000000002"
+        );
+        assert_eq!(json[0]["annotations"][0]["entry"], "20240101T110000Z");
+        assert!(json[0].get("annotation-entries").is_none());
+        assert_eq!(json[1]["uuid"], "00000000-0000-4000-8000-000000000002");
+        assert_eq!(json[0]["estimate"], 3);
+        assert_eq!(json[0]["priority"], "H");
+        assert_eq!(json[1]["status"], "completed");
+    }
+}
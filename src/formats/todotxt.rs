@@ -0,0 +1,223 @@
+use chrono::{DateTime, Local, NaiveDate};
+
+use super::{local_date, Format};
+use crate::{state::Task, Result};
+
+/// Tasks stored as lines of <http://todotxt.org>
+///
+/// Priority, dates, the last `+project`, `@context`s and `due:` map onto
+/// task fields, `uid:` is kept as the uid in `Task::extra`. Other `key:value`
+/// extensions are kept in order under [`EXTENSIONS_KEY`] and the remaining
+/// words, including any other projects, stay in the description. The project
+/// is written at the end again, so lines round-trip unchanged
+pub struct TodoTxt;
+
+/// Key in `Task::extra` holding the unknown extensions of a line as they were
+/// written, repeated keys included
+const EXTENSIONS_KEY: &str = "todotxt:extensions";
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+fn parse_date(date: &str) -> Option<DateTime<Local>> {
+    NaiveDate::parse_from_str(date, DATE_FORMAT)
+        .ok()
+        .and_then(local_date)
+}
+
+fn format_date(date: DateTime<Local>) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
+
+/// Parse a leading `(A) `
+fn parse_priority(text: &str) -> Option<(char, &str)> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some('('), Some(priority), Some(')'), Some(' ')) if priority.is_ascii_uppercase() => {
+            Some((priority, &text[4..]))
+        }
+        _ => None,
+    }
+}
+
+/// Parse a leading date followed by a space
+fn parse_leading_date(text: &str) -> Option<(DateTime<Local>, &str)> {
+    let (date, rest) = text.split_once(' ').unwrap_or((text, ""));
+    Some((parse_date(date)?, rest))
+}
+
+/// Split a `key:value` extension, urls like `http://` are not extensions
+fn parse_extension(word: &str) -> Option<(&str, &str)> {
+    let (key, value) = word.split_once(':')?;
+    if key.is_empty() || value.is_empty() || value.contains(':') || value.starts_with("//") {
+        return None;
+    }
+    Some((key, value))
+}
+
+/// Make sure a value can be written as a single word
+fn token(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join("-")
+}
+
+fn parse_line(line: &str) -> Task {
+    let mut task = Task {
+        last_updated: Local::now(),
+        ..Default::default()
+    };
+    let mut rest = line.trim();
+    if let Some(after) = rest.strip_prefix("x ") {
+        task.completed = true;
+        rest = after;
+    }
+    if let Some((priority, after)) = parse_priority(rest) {
+        task.priority = Some(priority);
+        rest = after;
+    }
+    if let Some((first, after)) = parse_leading_date(rest) {
+        rest = after;
+        if task.completed {
+            task.completed_on = Some(first);
+            if let Some((created, after)) = parse_leading_date(rest) {
+                task.created = Some(created);
+                rest = after;
+            }
+        } else {
+            task.created = Some(first);
+        }
+    }
+
+    let is_project = |word: &&str| word.len() > 1 && word.starts_with('+');
+    let all_words: Vec<&str> = rest.split_whitespace().collect();
+    let project_idx = all_words.iter().rposition(is_project);
+    let mut words = Vec::new();
+    let mut extensions = Vec::new();
+    for (idx, word) in all_words.into_iter().enumerate() {
+        if Some(idx) == project_idx {
+            task.project = Some(word[1..].to_string());
+            continue;
+        }
+        if let Some(context) = word.strip_prefix('@').filter(|context| !context.is_empty()) {
+            task.tags.push(context.to_string());
+            continue;
+        }
+        if let Some((key, value)) = parse_extension(word) {
+            match key {
+                "due" => {
+                    if let Some(due) = parse_date(value) {
+                        task.due = Some(due);
+                        continue;
+                    }
+                }
+                "pri" if task.priority.is_none() && value.len() == 1 => {
+                    task.priority = value.chars().next();
+                    continue;
+                }
+                "uid" => {
+                    task.extra.insert(key.to_string(), value.to_string());
+                    continue;
+                }
+                _ => {
+                    extensions.push(word);
+                    continue;
+                }
+            }
+        }
+        words.push(word);
+    }
+    task.desc = words.join(" ");
+    if !extensions.is_empty() {
+        task.extra
+            .insert(EXTENSIONS_KEY.to_string(), extensions.join(" "));
+    }
+    task
+}
+
+fn render_task(task: &Task) -> String {
+    let mut parts = Vec::new();
+    if task.completed {
+        parts.push("x".to_string());
+        parts.push(format_date(task.completed_on.unwrap_or(task.last_updated)));
+    } else if let Some(priority) = task.priority {
+        parts.push(format!("({priority})"));
+    }
+    if let Some(created) = task.created {
+        parts.push(format_date(created));
+    }
+    if !task.desc.is_empty() {
+        parts.push(task.desc.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+    if let Some(project) = &task.project {
+        parts.push(format!("+{}", token(project)));
+    }
+    for tag in &task.tags {
+        parts.push(format!("@{}", token(tag)));
+    }
+    if let Some(due) = task.due {
+        parts.push(format!("due:{}", format_date(due)));
+    }
+    if let (true, Some(priority)) = (task.completed, task.priority) {
+        parts.push(format!("pri:{priority}"));
+    }
+    if let Some(extensions) = task.extra.get(EXTENSIONS_KEY) {
+        parts.push(extensions.clone());
+    }
+    // extensions from other formats may not fit in a single word
+    for (key, value) in &task.extra {
+        if !key.contains(':') && !value.contains(':') {
+            parts.push(format!("{}:{}", token(key), token(value)));
+        }
+    }
+    parts.join(" ")
+}
+
+impl Format for TodoTxt {
+    fn parse(&self, input: &str) -> Result<Vec<Task>> {
+        Ok(input
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(parse_line)
+            .collect())
+    }
+
+    fn render(&self, tasks: &[&Task]) -> Result<String> {
+        Ok(tasks.iter().map(|task| render_task(task) + "\n").collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_parse() {
+        let task = parse_line(
+            "(A) 2024-01-01 Call mom +family +phone @home due:2024-01-05 rec:1w http://x.io",
+        );
+        assert_eq!(task.priority, Some('A'));
+        assert_eq!(task.created, parse_date("2024-01-01"));
+        assert_eq!(task.project.as_deref(), Some("phone"));
+        assert_eq!(task.tags, ["home"]);
+        assert_eq!(task.due, parse_date("2024-01-05"));
+        assert_eq!(task.extra[EXTENSIONS_KEY], "rec:1w");
+        assert_eq!(task.desc, "Call mom +family http://x.io");
+        assert!(!task.completed);
+
+        let task = parse_line("x 2024-01-03 2024-01-01 Pay rent pri:B");
+        assert!(task.completed);
+        assert_eq!(task.completed_on, parse_date("2024-01-03"));
+        assert_eq!(task.created, parse_date("2024-01-01"));
+        assert_eq!(task.priority, Some('B'));
+    }
+
+    #[test]
+    fn check_round_trip() {
+        for line in [
+            "(A) 2024-01-01 Call mom +phone +family @home due:2024-01-05 rec:1w",
+            "x 2024-01-03 2024-01-01 Pay rent pri:B",
+            "Water plants t:2024-01-02 rec:1w t:2024-01-09",
+            "Plain task",
+        ] {
+            assert_eq!(render_task(&parse_line(line)), line);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use crate::state::Task;
+
+/// Maximum number of steps kept in the undo history
+pub const MAX_STEPS: usize = 100;
+
+/// A single reversible change made to the state
+#[derive(Clone, PartialEq)]
+pub enum Operation {
+    /// A task was inserted at the given position
+    Insert { index: usize, task: Task },
+    /// A task was removed from the given position
+    Remove { index: usize, task: Task },
+    /// A task was changed in place
+    Update { before: Box<Task>, after: Box<Task> },
+    /// A task was deleted by the user and moved from the given position to the trash
+    Trash { index: usize, task: Task },
+    /// A task was brought back from the trash to the given position
+    Restore { index: usize, task: Task },
+}
+
+impl Operation {
+    /// Operation changing a task in place
+    pub fn update(before: Task, after: Task) -> Self {
+        Self::Update {
+            before: Box::new(before),
+            after: Box::new(after),
+        }
+    }
+
+    /// The operation which reverts this one
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::Insert { index, task } => Self::Remove {
+                index: *index,
+                task: task.clone(),
+            },
+            Self::Remove { index, task } => Self::Insert {
+                index: *index,
+                task: task.clone(),
+            },
+            Self::Update { before, after } => Self::Update {
+                before: after.clone(),
+                after: before.clone(),
+            },
+            Self::Trash { index, task } => Self::Restore {
+                index: *index,
+                task: task.clone(),
+            },
+            Self::Restore { index, task } => Self::Trash {
+                index: *index,
+                task: task.clone(),
+            },
+        }
+    }
+
+    /// Human readable summary of the operation
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Insert { task, .. } => format!("add task {} \"{}\"", task.id, task.desc),
+            Self::Remove { task, .. } => format!("remove task {} \"{}\"", task.id, task.desc),
+            Self::Trash { task, .. } => format!("trash task {} \"{}\"", task.id, task.desc),
+            Self::Restore { task, .. } => format!("restore task {} \"{}\"", task.id, task.desc),
+            Self::Update { before, after } => {
+                if before.completed != after.completed {
+                    format!("toggle status of task {}", after.id)
+                } else {
+                    format!("edit task {} \"{}\"", after.id, after.desc)
+                }
+            }
+        }
+    }
+}
+
+/// A group of operations which are undone and redone together
+pub type Step = Vec<Operation>;
+
+/// Bounded undo and redo stacks of the changes made to the state
+#[derive(Default)]
+pub struct History {
+    pub undo: Vec<Step>,
+    pub redo: Vec<Step>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember a new step, this invalidates everything that could be redone
+    pub fn record(&mut self, step: Step) {
+        if step.is_empty() {
+            return;
+        }
+        self.undo.push(step);
+        self.redo.clear();
+        if self.undo.len() > MAX_STEPS {
+            let excess = self.undo.len() - MAX_STEPS;
+            self.undo.drain(..excess);
+        }
+    }
+}
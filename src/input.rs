@@ -0,0 +1,393 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Text being typed, with a cursor
+///
+/// The cursor is a byte index which always sits between grapheme clusters, so
+/// an emoji or a letter with combining accents moves and deletes as one
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct Input {
+    text: String,
+    cursor: usize,
+    /// whether the text can have line breaks
+    multiline: bool,
+    /// columns available to the text, longer lines are wrapped when it is not 0
+    pub width: usize,
+    /// first row shown, kept by the view to follow the cursor
+    pub scroll: usize,
+}
+
+impl Input {
+    /// Input holding some text with the cursor at its end
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            cursor: text.len(),
+            ..Default::default()
+        }
+    }
+
+    /// Input which can hold several lines of text
+    pub fn multiline(text: &str) -> Self {
+        Self {
+            multiline: true,
+            ..Self::new(text)
+        }
+    }
+
+    pub fn is_multiline(&self) -> bool {
+        self.multiline
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Number of characters, counted as graphemes, and of lines
+    pub fn counts(&self) -> (usize, usize) {
+        (
+            self.text.graphemes(true).count(),
+            self.text.split('\n').count(),
+        )
+    }
+
+    /// Width in columns of the text before the cursor on its row
+    pub fn cursor_column(&self) -> usize {
+        let (row, _) = self.cursor_position();
+        self.text[row.0..self.cursor].width()
+    }
+
+    /// Byte ranges of the rows the text is shown on, without the line breaks
+    ///
+    /// Lines longer than `width` are wrapped after the last space which fits,
+    /// or anywhere if a word does not fit on its own
+    pub fn rows(&self) -> Vec<(usize, usize)> {
+        let mut rows = Vec::new();
+        let mut base = 0;
+        for line in self.text.split('\n') {
+            let mut start = 0;
+            let mut column = 0;
+            let mut after_space = None;
+            for (idx, grapheme) in line.grapheme_indices(true) {
+                let width = grapheme.width();
+                if self.width > 0 && column + width > self.width && idx > start {
+                    let end = after_space.filter(|end| *end > start).unwrap_or(idx);
+                    rows.push((base + start, base + end));
+                    column = line[end..idx].width();
+                    start = end;
+                    after_space = None;
+                }
+                column += width;
+                if grapheme == " " {
+                    after_space = Some(idx + 1);
+                }
+            }
+            rows.push((base + start, base + line.len()));
+            base += line.len() + 1;
+        }
+        rows
+    }
+
+    /// Row of the cursor and its index, a cursor where a line wraps is on the next row
+    fn cursor_position(&self) -> ((usize, usize), usize) {
+        let rows = self.rows();
+        let idx = rows
+            .iter()
+            .rposition(|(start, _)| *start <= self.cursor)
+            .unwrap_or(0);
+        (rows[idx], idx)
+    }
+
+    /// Index of the row holding the cursor
+    pub fn cursor_row(&self) -> usize {
+        self.cursor_position().1
+    }
+
+    /// Move the cursor to the row above or below, keeping its column
+    fn move_row(&mut self, down: bool) {
+        let rows = self.rows();
+        let (_, idx) = self.cursor_position();
+        let target = match (down, idx) {
+            (false, 0) => return,
+            (false, idx) => idx - 1,
+            (true, idx) if idx + 1 >= rows.len() => return,
+            (true, idx) => idx + 1,
+        };
+        let column = self.cursor_column();
+        let (start, end) = rows[target];
+        // a wrapped row ends where the next one starts, so the cursor stays before its end
+        let wrapped = rows.get(target + 1).is_some_and(|next| next.0 == end);
+        let mut cursor = start;
+        let mut width = 0;
+        for (idx, grapheme) in self.text[start..end].grapheme_indices(true) {
+            let last = start + idx + grapheme.len() == end;
+            width += grapheme.width();
+            if width > column || (wrapped && last) {
+                break;
+            }
+            cursor = start + idx + grapheme.len();
+        }
+        self.cursor = cursor;
+    }
+
+    /// Start of the line holding the cursor
+    fn line_start(&self) -> usize {
+        self.text[..self.cursor]
+            .rfind('\n')
+            .map_or(0, |idx| idx + 1)
+    }
+
+    /// End of the line holding the cursor
+    fn line_end(&self) -> usize {
+        self.text[self.cursor..]
+            .find('\n')
+            .map_or(self.text.len(), |idx| self.cursor + idx)
+    }
+
+    /// Start of the grapheme before the cursor
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(idx, _)| idx)
+    }
+
+    /// End of the grapheme after the cursor
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |grapheme| self.cursor + grapheme.len())
+    }
+
+    fn is_word(grapheme: &str) -> bool {
+        grapheme.chars().next().is_some_and(char::is_alphanumeric)
+    }
+
+    /// Start of the word before the cursor, spaces and punctuation are skipped first
+    fn prev_word(&self) -> usize {
+        let mut graphemes = self.text[..self.cursor]
+            .grapheme_indices(true)
+            .rev()
+            .peekable();
+        while graphemes
+            .next_if(|(_, grapheme)| !Self::is_word(grapheme))
+            .is_some()
+        {}
+        let mut start = graphemes.peek().map_or(0, |(idx, _)| *idx);
+        for (idx, grapheme) in graphemes {
+            if !Self::is_word(grapheme) {
+                break;
+            }
+            start = idx;
+        }
+        start
+    }
+
+    /// End of the word after the cursor, spaces and punctuation are skipped first
+    fn next_word(&self) -> usize {
+        let mut graphemes = self.text[self.cursor..].grapheme_indices(true).peekable();
+        while graphemes
+            .next_if(|(_, grapheme)| !Self::is_word(grapheme))
+            .is_some()
+        {}
+        let mut end = self.text.len() - self.cursor;
+        for (idx, grapheme) in graphemes {
+            if !Self::is_word(grapheme) {
+                end = idx;
+                break;
+            }
+        }
+        self.cursor + end
+    }
+
+    pub fn insert(&mut self, ch: char) {
+        self.text.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+    }
+
+    /// Start a new line, single line inputs ignore it
+    pub fn newline(&mut self) {
+        if self.multiline {
+            self.insert('\n');
+        }
+    }
+
+    /// Insert pasted text, line breaks become spaces unless the input is multiline
+    pub fn insert_str(&mut self, text: &str) {
+        let text = text.trim_end_matches(['\r', '\n']).replace("\r\n", "\n");
+        let text = if self.multiline {
+            text.replace(['\r'], "\n").replace('\t', " ")
+        } else {
+            text.replace(['\r', '\n', '\t'], " ")
+        };
+        self.text.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+    }
+
+    /// Delete the grapheme before the cursor
+    pub fn backspace(&mut self) {
+        let start = self.prev_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Delete the grapheme after the cursor
+    pub fn delete(&mut self) {
+        let end = self.next_boundary();
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.prev_boundary();
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = self.next_boundary();
+    }
+
+    /// Move to the start of the line
+    pub fn home(&mut self) {
+        self.cursor = self.line_start();
+    }
+
+    /// Move to the end of the line
+    pub fn end(&mut self) {
+        self.cursor = self.line_end();
+    }
+
+    pub fn word_left(&mut self) {
+        self.cursor = self.prev_word();
+    }
+
+    pub fn word_right(&mut self) {
+        self.cursor = self.next_word();
+    }
+
+    /// Delete the word before the cursor
+    pub fn delete_word(&mut self) {
+        let start = self.prev_word();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Delete everything before the cursor on its line
+    pub fn delete_to_start(&mut self) {
+        let start = self.line_start();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Apply a key press, returns whether the key was used
+    ///
+    /// Keys follow the readline conventions: Ctrl-W and Ctrl-U delete, Alt-B and
+    /// Alt-F move by words, as do Ctrl-Left and Ctrl-Right. Multiline inputs
+    /// also take Up and Down, and Shift-Enter or Alt-Enter for a new line
+    pub fn handle(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        match key.code {
+            KeyCode::Char('w') if ctrl => self.delete_word(),
+            KeyCode::Char('u') if ctrl => self.delete_to_start(),
+            KeyCode::Char('a') if ctrl => self.home(),
+            KeyCode::Char('e') if ctrl => self.end(),
+            KeyCode::Char('b') if alt => self.word_left(),
+            KeyCode::Char('f') if alt => self.word_right(),
+            KeyCode::Backspace if ctrl || alt => self.delete_word(),
+            KeyCode::Char(_) if ctrl || alt => return false,
+            KeyCode::Char(ch) => self.insert(ch),
+            KeyCode::Backspace => self.backspace(),
+            KeyCode::Delete => self.delete(),
+            KeyCode::Left if ctrl || alt => self.word_left(),
+            KeyCode::Right if ctrl || alt => self.word_right(),
+            KeyCode::Left => self.left(),
+            KeyCode::Right => self.right(),
+            KeyCode::Home => self.home(),
+            KeyCode::End => self.end(),
+            KeyCode::Up if self.multiline => self.move_row(false),
+            KeyCode::Down if self.multiline => self.move_row(true),
+            KeyCode::Enter
+                if self.multiline
+                    && key
+                        .modifiers
+                        .intersects(KeyModifiers::SHIFT | KeyModifiers::ALT) =>
+            {
+                self.newline()
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_editing() {
+        // `e` with a combining accent and a family emoji are single graphemes
+        let mut input = Input::new("cafe\u{301} \u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467} time");
+        input.word_left();
+        assert_eq!(input.cursor, input.text.len() - 4);
+        input.left();
+        input.backspace();
+        assert_eq!(input.text(), "cafe\u{301}  time");
+        input.home();
+        input.word_right();
+        input.left();
+        input.delete();
+        assert_eq!(input.text(), "caf  time");
+        assert_eq!(input.cursor_column(), 3);
+
+        input.end();
+        input.delete_word();
+        assert_eq!(input.text(), "caf  ");
+        input.insert_str("new\r\nline\n");
+        assert_eq!(input.text(), "caf  new line");
+        input.word_left();
+        input.delete_to_start();
+        assert_eq!(input.text(), "line");
+        assert!(!input.handle(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL)));
+    }
+
+    #[test]
+    fn check_wrapping() {
+        let mut input = Input::multiline("one two three\nfour");
+        input.width = 9;
+        let rows: Vec<&str> = input
+            .rows()
+            .into_iter()
+            .map(|(start, end)| &input.text[start..end])
+            .collect();
+        assert_eq!(rows, ["one two ", "three", "four"]);
+        assert_eq!(input.cursor_row(), 2);
+
+        input.move_row(false);
+        assert_eq!((input.cursor_row(), input.cursor_column()), (1, 4));
+        input.move_row(false);
+        assert_eq!((input.cursor_row(), input.cursor_column()), (0, 4));
+        input.end();
+        // the end of a wrapped row is the start of the next one
+        assert_eq!(input.cursor_row(), 1);
+        input.home();
+        input.newline();
+        assert_eq!(input.text(), "\none two three\nfour");
+        input.insert_str("a\r\nb");
+        assert_eq!(input.text(), "\na\nbone two three\nfour");
+
+        input.width = 3;
+        input.text = "abcdefg".to_string();
+        let rows: Vec<&str> = input
+            .rows()
+            .into_iter()
+            .map(|(start, end)| &input.text[start..end])
+            .collect();
+        assert_eq!(rows, ["abc", "def", "g"]);
+    }
+}
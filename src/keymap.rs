@@ -0,0 +1,440 @@
+use std::{fmt, str::FromStr};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config::Keys;
+
+/// Name of the keymap file, it is read from the folder of the configuration file
+pub(crate) const KEYMAP_NAME: &str = "keymap.toml";
+
+/// Actions in the task list and the trash which can be bound to keys
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Edit,
+    Delete,
+    NewTask,
+    Quit,
+    Toggle,
+    Undo,
+    Redo,
+    Trash,
+    NextTheme,
+    MoveUp,
+    MoveDown,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Details,
+    Mark,
+    Visual,
+    Unmark,
+    Tag,
+    Project,
+    Priority,
+    NextTab,
+    PrevTab,
+    Search,
+    NextMatch,
+    PrevMatch,
+    Restore,
+    Purge,
+}
+
+impl Action {
+    /// Every action, in the order they are shown in the footer
+    pub const ALL: [Action; 29] = [
+        Action::Edit,
+        Action::Delete,
+        Action::NewTask,
+        Action::Quit,
+        Action::Toggle,
+        Action::Undo,
+        Action::Redo,
+        Action::Trash,
+        Action::Details,
+        Action::Mark,
+        Action::Visual,
+        Action::Unmark,
+        Action::Tag,
+        Action::Project,
+        Action::Priority,
+        Action::NextTab,
+        Action::PrevTab,
+        Action::Search,
+        Action::NextMatch,
+        Action::PrevMatch,
+        Action::NextTheme,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::PageUp,
+        Action::PageDown,
+        Action::Top,
+        Action::Bottom,
+        Action::Restore,
+        Action::Purge,
+    ];
+
+    /// Actions of the trash, which are left out of the footer of the task list
+    pub const TRASH: [Action; 2] = [Action::Restore, Action::Purge];
+
+    /// Name of the action in the configuration and the keymap file
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::NewTask => "new",
+            Action::Edit => "edit",
+            Action::Delete => "delete",
+            Action::Toggle => "toggle",
+            Action::MoveUp => "up",
+            Action::MoveDown => "down",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Trash => "trash",
+            Action::NextTheme => "theme",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::Details => "details",
+            Action::Mark => "mark",
+            Action::Visual => "visual",
+            Action::Unmark => "unmark",
+            Action::Tag => "tag",
+            Action::Project => "project",
+            Action::Priority => "priority",
+            Action::NextTab => "next_tab",
+            Action::PrevTab => "prev_tab",
+            Action::Search => "search",
+            Action::NextMatch => "next_match",
+            Action::PrevMatch => "prev_match",
+            Action::Restore => "restore",
+            Action::Purge => "purge",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    /// Description of the action in the footer
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::NewTask => "New",
+            Action::Edit => "Edit",
+            Action::Delete => "Delete",
+            Action::Toggle => "Toggle status",
+            Action::MoveUp => "Up",
+            Action::MoveDown => "Down",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::Trash => "Trash",
+            Action::NextTheme => "Theme",
+            Action::PageUp => "Page up",
+            Action::PageDown => "Page down",
+            Action::Top => "Top",
+            Action::Bottom => "Bottom",
+            Action::Details => "Details",
+            Action::Mark => "Mark",
+            Action::Visual => "Visual",
+            Action::Unmark => "Unmark all",
+            Action::Tag => "Tags",
+            Action::Project => "Project",
+            Action::Priority => "Priority",
+            Action::NextTab => "Next tab",
+            Action::PrevTab => "Previous tab",
+            Action::Search => "Search",
+            Action::NextMatch => "Next match",
+            Action::PrevMatch => "Previous match",
+            Action::Restore => "Restore",
+            Action::Purge => "Delete forever",
+        }
+    }
+}
+
+/// A key with its modifiers
+#[derive(PartialEq, Debug)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+/// Key codes which have a name, the first name of a key is the one shown
+const NAMED_KEYS: [(&str, KeyCode); 19] = [
+    ("enter", KeyCode::Enter),
+    ("return", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("escape", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("del", KeyCode::Delete),
+    ("space", KeyCode::Char(' ')),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("insert", KeyCode::Insert),
+    ("backtab", KeyCode::BackTab),
+];
+
+fn named_key(name: &str) -> Option<KeyCode> {
+    let name = name.to_ascii_lowercase();
+    NAMED_KEYS
+        .iter()
+        .find(|(key, _)| *key == name)
+        .map(|(_, code)| *code)
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(spec: &str) -> std::result::Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = spec;
+        // a lone `-` is a key, not a separator
+        while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "a" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier `{modifier}` in key `{spec}`")),
+            };
+            rest = key;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(ch), None) => KeyCode::Char(ch),
+            _ => named_key(rest).ok_or_else(|| format!("unknown key `{spec}`"))?,
+        };
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "^")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) && !matches!(self.code, KeyCode::Char(_)) {
+            write!(f, "shift-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(ch) => write!(f, "{ch}"),
+            KeyCode::Up => write!(f, "\u{2191}"),
+            KeyCode::Down => write!(f, "\u{2193}"),
+            KeyCode::Left => write!(f, "\u{2190}"),
+            KeyCode::Right => write!(f, "\u{2192}"),
+            KeyCode::Enter => write!(f, "Enter"),
+            KeyCode::Esc => write!(f, "Esc"),
+            KeyCode::PageUp => write!(f, "PgUp"),
+            KeyCode::PageDown => write!(f, "PgDn"),
+            code => {
+                let name = NAMED_KEYS
+                    .iter()
+                    .find(|(_, named)| *named == code)
+                    .map_or("?", |(name, _)| name);
+                let mut chars = name.chars();
+                if let Some(first) = chars.next() {
+                    write!(f, "{}{}", first.to_ascii_uppercase(), chars.as_str())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Key {
+    /// Whether a key press matches this key
+    ///
+    /// Shift is part of the character itself, or of BackTab, so it is only
+    /// compared for other keys
+    pub fn matches(&self, event: &KeyEvent) -> bool {
+        let ignored = match event.code {
+            KeyCode::Char(_) | KeyCode::BackTab => KeyModifiers::SHIFT,
+            _ => KeyModifiers::NONE,
+        };
+        self.code == event.code
+            && self.modifiers.difference(ignored) == event.modifiers.difference(ignored)
+    }
+}
+
+/// Parse a binding, which is a single key or a chord of keys pressed one after another
+///
+/// The keys of a chord are separated by spaces like `g g` or `ctrl-x ctrl-s`,
+/// characters can also be run together like `gg` when they do not spell the
+/// name of a key
+pub fn parse_binding(spec: &str) -> std::result::Result<Vec<Key>, String> {
+    let words: Vec<&str> = spec.split_whitespace().collect();
+    match words[..] {
+        [] => Err("empty key binding".to_string()),
+        [word] if word.chars().count() > 1 && !word.contains('-') && named_key(word).is_none() => {
+            Ok(word
+                .chars()
+                .map(|ch| Key::from_str(&ch.to_string()))
+                .collect::<Result<_, _>>()?)
+        }
+        _ => words.into_iter().map(Key::from_str).collect(),
+    }
+}
+
+/// Bindings of the actions along with the keys of a chord being typed
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, Action)>,
+    pending: Vec<KeyEvent>,
+}
+
+impl Keymap {
+    /// Keymap of the given keys, invalid bindings are left out
+    ///
+    /// Bindings are checked when the configuration is loaded
+    pub fn new(keys: &Keys) -> Self {
+        let bindings = Action::ALL
+            .into_iter()
+            .flat_map(|action| {
+                keys.bindings(action)
+                    .iter()
+                    .filter_map(move |spec| Some((parse_binding(spec).ok()?, action)))
+            })
+            .collect();
+        Self {
+            bindings,
+            pending: Vec::new(),
+        }
+    }
+
+    fn starts_with(keys: &[Key], events: &[KeyEvent]) -> bool {
+        keys.len() >= events.len()
+            && keys
+                .iter()
+                .zip(events)
+                .all(|(key, event)| key.matches(event))
+    }
+
+    /// Feed a key press, the action is returned once a binding is complete
+    ///
+    /// A key which starts a longer binding waits for the rest of it. Keys which
+    /// do not continue any binding drop the chord typed so far.
+    pub fn press(&mut self, event: KeyEvent) -> Option<Action> {
+        self.pending.push(event);
+        loop {
+            let pending = &self.pending;
+            let exact = self
+                .bindings
+                .iter()
+                .find(|(keys, _)| keys.len() == pending.len() && Self::starts_with(keys, pending));
+            let longer = self
+                .bindings
+                .iter()
+                .any(|(keys, _)| keys.len() > pending.len() && Self::starts_with(keys, pending));
+            if longer {
+                return None;
+            }
+            if let Some((_, action)) = exact {
+                self.pending.clear();
+                return Some(*action);
+            }
+            if self.pending.len() == 1 {
+                self.pending.clear();
+                return None;
+            }
+            // the key may still start a binding of its own
+            self.pending = vec![event];
+        }
+    }
+
+    /// The first binding of an action, like `dd`
+    pub fn first_binding(&self, action: Action) -> Option<String> {
+        let (keys, _) = self.bindings.iter().find(|(_, bound)| *bound == action)?;
+        Some(keys.iter().map(|key| key.to_string()).collect())
+    }
+
+    /// The first binding of every action of the task list along with its label, like `e:Edit`
+    pub fn footer_items(&self) -> Vec<(String, Action)> {
+        Action::ALL
+            .into_iter()
+            .filter(|action| !Action::TRASH.contains(action))
+            .filter_map(|action| {
+                let keys = self.first_binding(action)?;
+                Some((format!("{keys}:{}", action.label()), action))
+            })
+            .collect()
+    }
+
+    /// Help line of the trash
+    pub fn trash_footer(&self) -> String {
+        let key = |action| self.first_binding(action).unwrap_or_default();
+        format!(
+            "{}:Restore \u{ff5c} {}:Delete forever \u{ff5c} {}/{}:Select \u{ff5c} Esc:Back",
+            key(Action::Restore),
+            key(Action::Purge),
+            key(Action::MoveUp),
+            key(Action::MoveDown)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_chords() {
+        let mut keys = Keys::default();
+        *keys.bindings_mut(Action::Delete) = vec!["x".to_string(), "dd".to_string()];
+        *keys.bindings_mut(Action::MoveUp) = vec!["g g".to_string()];
+        let mut keymap = Keymap::new(&keys);
+        let press = |keymap: &mut Keymap, ch| {
+            keymap.press(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE))
+        };
+
+        assert_eq!(press(&mut keymap, 'x'), Some(Action::Delete));
+        assert_eq!(press(&mut keymap, 'd'), None);
+        assert_eq!(press(&mut keymap, 'd'), Some(Action::Delete));
+        // a broken chord starts over with the last key
+        assert_eq!(press(&mut keymap, 'g'), None);
+        assert_eq!(press(&mut keymap, 'q'), Some(Action::Quit));
+        assert!(keymap.pending.is_empty());
+        assert_eq!(
+            keymap.press(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL)),
+            Some(Action::Redo)
+        );
+
+        assert_eq!(parse_binding("end").unwrap().len(), 1);
+        assert_eq!(parse_binding("e n d").unwrap().len(), 3);
+        assert!(parse_binding("hyper-x").is_err());
+        let items: Vec<String> = keymap
+            .footer_items()
+            .into_iter()
+            .map(|(item, _)| item)
+            .collect();
+        assert_eq!(items[..3], ["e:Edit", "x:Delete", "i:New"]);
+    }
+
+    #[test]
+    fn check_trash_keys() {
+        let mut keys = Keys::default();
+        *keys.bindings_mut(Action::Restore) = vec!["R".to_string()];
+        let mut keymap = Keymap::new(&keys);
+        assert_eq!(
+            keymap.press(KeyEvent::new(KeyCode::Char('R'), KeyModifiers::SHIFT)),
+            Some(Action::Restore)
+        );
+        assert!(keymap
+            .trash_footer()
+            .starts_with("R:Restore \u{ff5c} D:Delete forever"));
+        assert!(keymap
+            .footer_items()
+            .iter()
+            .all(|(_, action)| *action != Action::Restore));
+    }
+}
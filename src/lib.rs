@@ -1,19 +1,47 @@
-use chrono::{DateTime, Datelike, Local};
+use chrono::{DateTime, Datelike, Duration, Local};
 
 /// Manage the status of the TUI application
 pub mod app;
 /// Manage the args passed in cli
 pub mod args;
+/// Rotating snapshots of tasks data which can be restored
+pub mod backup;
+/// Settings loaded from `config.toml`
+pub mod config;
+/// Encryption at rest of the files holding tasks
+pub mod crypto;
 /// Manage tui event handling
 pub mod events;
 /// File management module for storing anf managing tasks
 pub mod files;
+/// Form for the fields of a task in the TUI
+pub mod form;
+/// Other file formats which tasks can be imported from and exported to
+pub mod formats;
+/// Undo and redo history of changes made to tasks
+pub mod history;
+/// Single line text input with a cursor used in the TUI
+pub mod input;
+/// Actions of the TUI and the keys bound to them
+pub mod keymap;
+/// Append-only per-device logs of changes, merged into the tasks on load
+pub mod oplog;
+/// Fuzzy search of the tasks in the TUI
+pub mod search;
 /// The current state of tasks as a buffer in tui
 pub mod state;
+/// Git backed history and synchronization of tasks between devices
+pub mod sync;
+/// Tabs of the task list in the TUI
+pub mod tabs;
+/// Colors of the TUI, built in or loaded from theme files
+pub mod theme;
 /// wrapper for all tui related functions
 pub mod tui;
 /// user interface for the tui
 pub mod ui;
+/// Autosave of the TUI and merging of changes made to the data file while it runs
+pub mod watch;
 
 /// Generic wrapper for Result type
 ///
@@ -29,10 +57,31 @@ pub fn get_id() -> Id {
 
 pub fn format_date(date: DateTime<Local>) -> String {
     let now_date = Local::now();
+    let general = &config::config().general;
     // different years
     if date.year() != now_date.year() {
-        format!("{}", date.format("%d/%m/%Y"))
+        format!("{}", date.format(&general.date_format_other_year))
     } else {
-        format!("{}", date.format("%d/%m, %H:%M"))
+        format!("{}", date.format(&general.date_format))
+    }
+}
+
+/// Parse a human friendly age like `30d`, `2w`, `12h` or `10s`
+pub fn parse_age(age: &str) -> std::result::Result<Duration, String> {
+    let age = age.trim();
+    let split = age
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(age.len());
+    let (amount, unit) = age.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .map_err(|_| format!("invalid age `{age}`, expected something like 30d"))?;
+    match unit {
+        "s" => Ok(Duration::seconds(amount)),
+        "m" => Ok(Duration::minutes(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        "d" | "" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(format!("unknown unit `{unit}`, use one of s, m, h, d or w")),
     }
 }
@@ -0,0 +1,506 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, TimeZone};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+
+use crate::{
+    config::{config, Backend},
+    crypto,
+    files::{data_file, no_metadata, task_from_record, task_record, TASK_HEADER},
+    get_id,
+    state::Task,
+    Id, Result,
+};
+
+/// Folder holding the operation log of every device, this is what should be shared
+pub const OPS_FOLDER: &str = "ops";
+/// File holding the name of this device, it must not be shared
+pub(crate) const DEVICE_NAME: &str = "device";
+const OPS_HEADER: [&str; 6] = ["millis", "counter", "device", "id", "field", "value"];
+/// Field of the operations removing a task
+const DELETED: &str = "deleted";
+/// Value of the removals which moved the task to the archive, it is empty for deletions
+const ARCHIVED: &str = "archived";
+/// A log is compacted once it has this many operations and at least half of
+/// them are superseded
+const COMPACT_AFTER: usize = 500;
+
+/// Hybrid logical clock of an operation
+///
+/// Physical time in milliseconds, with a counter ordering operations which
+/// happened in the same millisecond or while the local clock was behind the
+/// clock of another device. Ties are broken by the name of the device, so
+/// every device orders operations the same way.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Clock {
+    pub millis: i64,
+    pub counter: u32,
+    pub device: String,
+}
+
+impl Clock {
+    /// A clock later than `last` and the current time
+    fn tick(last: Option<&Clock>, device: &str) -> Self {
+        let now = Local::now().timestamp_millis();
+        match last {
+            Some(last) if last.millis >= now => Self {
+                millis: last.millis,
+                counter: last.counter + 1,
+                device: device.to_string(),
+            },
+            _ => Self {
+                millis: now,
+                counter: 0,
+                device: device.to_string(),
+            },
+        }
+    }
+}
+
+/// A change to a single field of a task
+///
+/// Fields are named and encoded like the columns of the data file. The
+/// latest operation on a field wins and a task exists as long as its
+/// latest operation is not a deletion.
+#[derive(Clone, Debug)]
+pub struct Op {
+    pub clock: Clock,
+    pub id: Id,
+    pub field: String,
+    pub value: String,
+}
+
+impl Op {
+    fn from_record(record: &StringRecord) -> Option<Self> {
+        Some(Self {
+            clock: Clock {
+                millis: record.get(0)?.parse().ok()?,
+                counter: record.get(1)?.parse().ok()?,
+                device: record.get(2)?.to_string(),
+            },
+            id: record.get(3)?.parse().ok()?,
+            field: record.get(4)?.to_string(),
+            value: record.get(5)?.to_string(),
+        })
+    }
+
+    fn record(&self) -> [String; 6] {
+        [
+            self.clock.millis.to_string(),
+            self.clock.counter.to_string(),
+            self.clock.device.clone(),
+            self.id.to_string(),
+            self.field.clone(),
+            self.value.clone(),
+        ]
+    }
+}
+
+fn ops_dir() -> Option<PathBuf> {
+    data_file(OPS_FOLDER)
+}
+
+/// Whether tasks are stored in the operation logs
+///
+/// Unless a backend is configured, the logs are used once they exist
+pub fn enabled() -> bool {
+    match config().storage.backend {
+        Some(backend) => backend == Backend::Oplog,
+        None => started(),
+    }
+}
+
+/// Whether the logs were created
+pub fn started() -> bool {
+    ops_dir().is_some_and(|dir| dir.is_dir())
+}
+
+/// Name of this device, created on first use
+pub(crate) fn device() -> Result<String> {
+    let path = data_file(DEVICE_NAME).ok_or_else(no_metadata)?;
+    if let Ok(device) = fs::read_to_string(&path) {
+        if !device.trim().is_empty() {
+            return Ok(device.trim().to_string());
+        }
+    }
+    let device: String = (0..8)
+        .map(|_| fastrand::alphanumeric().to_ascii_lowercase())
+        .collect();
+    fs::write(path, &device)?;
+    Ok(device)
+}
+
+fn log_path(device: &str) -> Result<PathBuf> {
+    Ok(ops_dir()
+        .ok_or_else(no_metadata)?
+        .join(format!("{device}.csv")))
+}
+
+/// Read the operations of a log
+///
+/// Records which cannot be read, like a line cut short while the file was
+/// being synced, are skipped
+fn read_log(path: &Path) -> Result<Vec<Op>> {
+    Ok(ops_from_csv(&crypto::read(path)?))
+}
+
+/// Operations stored in the contents of a log, see [`read_log`]
+pub(crate) fn ops_from_csv(data: &[u8]) -> Vec<Op> {
+    let mut reader = ReaderBuilder::new().flexible(true).from_reader(data);
+    reader
+        .records()
+        .filter_map(|record| Op::from_record(&record.ok()?))
+        .collect()
+}
+
+/// Contents of a whole log holding the given operations
+pub(crate) fn ops_to_csv(ops: &[Op]) -> Result<Vec<u8>> {
+    let mut writer = WriterBuilder::new().from_writer(Vec::new());
+    writer.write_record(OPS_HEADER)?;
+    for op in ops {
+        writer.write_record(op.record())?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// Operations from the logs of all devices
+fn read_all() -> Result<Vec<Op>> {
+    let mut ops = Vec::new();
+    for entry in fs::read_dir(ops_dir().ok_or_else(no_metadata)?)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "csv") {
+            ops.extend(read_log(&path)?);
+        }
+    }
+    Ok(ops)
+}
+
+fn write_ops(path: &Path, ops: &[Op], append: bool) -> Result<()> {
+    let exists = path.exists();
+    if append && exists && crypto::is_encrypted() {
+        // an encrypted log can only be written as a whole
+        let mut all = read_log(path)?;
+        all.extend_from_slice(ops);
+        return write_ops(path, &all, false);
+    }
+    let mut writer = WriterBuilder::new().from_writer(Vec::new());
+    if !append || !exists {
+        writer.write_record(OPS_HEADER)?;
+    }
+    for op in ops {
+        writer.write_record(op.record())?;
+    }
+    let data = writer.into_inner()?;
+    if append && exists {
+        OpenOptions::new()
+            .append(true)
+            .open(path)?
+            .write_all(&data)?;
+    } else {
+        crypto::write(path, &data)?;
+    }
+    Ok(())
+}
+
+/// Latest operation for each field of each task
+fn latest(ops: &[Op]) -> BTreeMap<(Id, &str), &Op> {
+    let mut latest: BTreeMap<(Id, &str), &Op> = BTreeMap::new();
+    for op in ops {
+        let entry = latest.entry((op.id, op.field.as_str())).or_insert(op);
+        if op.clock > entry.clock {
+            *entry = op;
+        }
+    }
+    latest
+}
+
+/// The tasks resulting from a set of operations
+///
+/// The result only depends on the operations, not on the order they are
+/// given in. Tasks are ordered from the most recently created.
+pub fn materialize(ops: &[Op]) -> Result<Vec<Task>> {
+    // clocks of the first and the last operation on each task
+    let mut span: HashMap<Id, (&Clock, &Op)> = HashMap::new();
+    for op in ops {
+        let entry = span.entry(op.id).or_insert((&op.clock, op));
+        if op.clock < *entry.0 {
+            entry.0 = &op.clock;
+        }
+        if op.clock > entry.1.clock {
+            entry.1 = op;
+        }
+    }
+    let latest = latest(ops);
+    let mut tasks = Vec::new();
+    for (id, (first, last)) in span {
+        if last.field == DELETED {
+            continue;
+        }
+        let mut record = task_record(&Task {
+            id,
+            last_updated: Local
+                .timestamp_millis_opt(first.millis)
+                .single()
+                .ok_or_else(|| format!("invalid time {} in the log of task {id}", first.millis))?,
+            ..Default::default()
+        });
+        for (idx, field) in TASK_HEADER.iter().enumerate().skip(1) {
+            if let Some(op) = latest.get(&(id, *field)) {
+                record[idx] = op.value.clone();
+            }
+        }
+        // the operation setting the creation time is only dropped if the
+        // time changes, so the order survives compaction
+        let created = latest.get(&(id, "created")).map_or(first, |op| &op.clock);
+        tasks.push((created, task_from_record(&StringRecord::from(record), 0)?));
+    }
+    tasks.sort_by(|(created, _), (other, _)| other.cmp(created));
+    Ok(tasks.into_iter().map(|(_, task)| task).collect())
+}
+
+/// Operations turning the tasks `before` into the tasks `after`
+pub fn diff(before: &[Task], after: &[Task], mut clock: impl FnMut() -> Clock) -> Vec<Op> {
+    let mut ops = Vec::new();
+    // the first task is the newest, so it is created last
+    for task in after.iter().rev() {
+        let previous = before
+            .iter()
+            .find(|other| other.id == task.id)
+            .map(task_record);
+        for (idx, value) in task_record(task).into_iter().enumerate().skip(1) {
+            if previous
+                .as_ref()
+                .is_none_or(|previous| previous[idx] != value)
+            {
+                ops.push(Op {
+                    clock: clock(),
+                    id: task.id,
+                    field: TASK_HEADER[idx].to_string(),
+                    value,
+                });
+            }
+        }
+    }
+    for task in before {
+        if !after.iter().any(|other| other.id == task.id) {
+            ops.push(Op {
+                clock: clock(),
+                id: task.id,
+                field: DELETED.to_string(),
+                value: String::new(),
+            });
+        }
+    }
+    ops
+}
+
+/// Operations found in either version of a log, each once and in order
+///
+/// A log is only written by its device, so an operation is told by its clock
+pub(crate) fn union(ours: Vec<Op>, theirs: Vec<Op>) -> Vec<Op> {
+    let mut ops = [ours, theirs].concat();
+    ops.sort_by(|op, other| op.clock.cmp(&other.clock));
+    ops.dedup_by(|op, other| op.clock == other.clock);
+    ops
+}
+
+/// Give a new id to the tasks a device added while another device added a
+/// different task under the same id
+///
+/// `base` holds the operations both sides had, `own` the log of the device
+/// and `theirs` the operations of the other side. Two tasks are told apart by
+/// their first operation. Returns the number of tasks which got a new id
+pub(crate) fn separate_ids(base: &[Op], own: &mut [Op], theirs: &[Op]) -> usize {
+    let known: HashSet<Id> = base.iter().map(|op| op.id).collect();
+    let first = |ops: &[Op]| {
+        let mut first: HashMap<Id, Clock> = HashMap::new();
+        for op in ops.iter().filter(|op| !known.contains(&op.id)) {
+            let clock = first.entry(op.id).or_insert_with(|| op.clock.clone());
+            if op.clock < *clock {
+                *clock = op.clock.clone();
+            }
+        }
+        first
+    };
+    let theirs_first = first(theirs);
+    let colliding: Vec<Id> = first(own)
+        .into_iter()
+        .filter(|(id, clock)| theirs_first.get(id).is_some_and(|other| other != clock))
+        .map(|(id, _)| id)
+        .collect();
+    let mut taken: HashSet<Id> = [base, &*own, theirs]
+        .into_iter()
+        .flatten()
+        .map(|op| op.id)
+        .collect();
+    for id in &colliding {
+        let new_id = loop {
+            let new_id = get_id();
+            if taken.insert(new_id) {
+                break new_id;
+            }
+        };
+        for op in own.iter_mut().filter(|op| op.id == *id) {
+            op.id = new_id;
+        }
+    }
+    colliding.len()
+}
+
+/// Tasks from the logs of all devices
+pub fn load() -> Result<Vec<Task>> {
+    materialize(&read_all()?)
+}
+
+/// Append the changes from `before` to `after` to the log of this device
+///
+/// The removals of the `archived` tasks are told apart from deletions
+pub fn record(before: &[Task], after: &[Task], archived: &[Id]) -> Result<()> {
+    let device = device()?;
+    let all = read_all()?;
+    let mut last = all.iter().map(|op| &op.clock).max().cloned();
+    let mut ops = diff(before, after, || {
+        let clock = Clock::tick(last.as_ref(), &device);
+        last = Some(clock.clone());
+        clock
+    });
+    for op in &mut ops {
+        if op.field == DELETED && archived.contains(&op.id) {
+            op.value = ARCHIVED.to_string();
+        }
+    }
+    if ops.is_empty() {
+        return Ok(());
+    }
+    write_ops(&log_path(&device)?, &ops, true)?;
+
+    let own = all.iter().filter(|op| op.clock.device == device).count() + ops.len();
+    if own >= COMPACT_AFTER {
+        let all = [all, ops].concat();
+        if compacted(&all, &device).len() * 2 <= own {
+            compact()?;
+        }
+    }
+    Ok(())
+}
+
+/// Operations of a device which are still needed
+///
+/// Every operation which is not the latest on its field can be dropped. The
+/// latest operation of each field is always kept by the device which made
+/// it, so logs of different devices can be compacted independently.
+fn compacted<'a>(ops: &'a [Op], device: &str) -> Vec<&'a Op> {
+    let mut kept: Vec<&Op> = latest(ops)
+        .into_values()
+        .filter(|op| op.clock.device == device)
+        .collect();
+    kept.sort_by(|op, other| op.clock.cmp(&other.clock));
+    kept
+}
+
+/// Rewrite the log of this device without superseded operations
+///
+/// Returns the number of operations before and after
+pub fn compact() -> Result<(usize, usize)> {
+    let device = device()?;
+    let path = log_path(&device)?;
+    if !path.exists() {
+        return Ok((0, 0));
+    }
+    let all = read_all()?;
+    let before = all.iter().filter(|op| op.clock.device == device).count();
+    let kept: Vec<Op> = compacted(&all, &device).into_iter().cloned().collect();
+    // write next to the log and swap, so that the log is never left half written
+    let temp = path.with_extension("csv.tmp");
+    write_ops(&temp, &kept, false)?;
+    fs::rename(temp, path)?;
+    Ok((before, kept.len()))
+}
+
+/// Start keeping tasks in operation logs
+///
+/// If other devices already share their logs, only the tasks they do not
+/// know about are added. Returns the number of tasks added to the logs.
+pub fn enable(tasks: &[Task]) -> Result<usize> {
+    fs::create_dir_all(ops_dir().ok_or_else(no_metadata)?)?;
+    let known = load()?;
+    let new: Vec<Task> = tasks
+        .iter()
+        .filter(|task| !known.iter().any(|other| other.id == task.id))
+        .cloned()
+        .collect();
+    record(&[], &new, &[])?;
+    Ok(new.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: Id, desc: &str) -> Task {
+        Task {
+            id,
+            desc: desc.to_string(),
+            created: Some(Local::now()),
+            ..Default::default()
+        }
+    }
+
+    /// Operations made on a device, after all the operations seen so far
+    fn changes(last: &mut Option<Clock>, device: &str, before: &[Task], after: &[Task]) -> Vec<Op> {
+        diff(before, after, || {
+            let clock = Clock::tick(last.as_ref(), device);
+            *last = Some(clock.clone());
+            clock
+        })
+    }
+
+    #[test]
+    fn check_merge_converges() {
+        let shared = vec![task(1, "shared")];
+        let mut last = None;
+        let base = changes(&mut last, "a", &[], &shared);
+
+        // both devices edit offline
+        let mut edited = shared[0].clone();
+        edited.desc = "edited on a".to_string();
+        let ops_a = changes(&mut last, "a", &shared, &[task(2, "new on a"), edited]);
+        let mut marked = shared[0].clone();
+        marked.completed = true;
+        let ops_b = changes(&mut last, "b", &shared, &[marked]);
+
+        let mut merged = [base.clone(), ops_a.clone(), ops_b.clone()].concat();
+        let tasks = materialize(&merged).unwrap();
+        merged.reverse();
+        let reversed = materialize(&merged).unwrap();
+        assert!(tasks == reversed);
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[1].desc, "edited on a");
+        assert!(tasks[1].completed);
+
+        // a deletion hides the task, compaction keeps the same result
+        let delete = changes(&mut last, "b", &tasks[1..], &[]);
+        let all = [base, ops_a, ops_b, delete].concat();
+        assert_eq!(materialize(&all).unwrap().len(), 1);
+        let compacted: Vec<Op> = ["a", "b"]
+            .iter()
+            .flat_map(|device| compacted(&all, device))
+            .cloned()
+            .collect();
+        assert!(compacted.len() < all.len());
+        assert!(materialize(&compacted).unwrap() == materialize(&all).unwrap());
+    }
+
+    #[test]
+    fn check_invalid_time() {
+        let mut last = None;
+        let mut ops = changes(&mut last, "a", &[], &[task(1, "one")]);
+        ops[0].clock.millis = i64::MIN;
+        assert!(materialize(&ops).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use sublime_fuzzy::best_match;
+
+use crate::state::{State, Task};
+
+/// A task matching a search
+pub struct Match {
+    /// index of the task in the list
+    pub idx: usize,
+    pub score: isize,
+    /// indices of the matched chars of the description
+    pub chars: Vec<usize>,
+}
+
+/// How well a task matches the query, along with the matched chars of its description
+///
+/// The score is the best one of the description, the notes, the project and
+/// the tags. The chars are empty when the description itself does not match
+pub fn score(query: &str, task: &Task) -> Option<(isize, Vec<usize>)> {
+    let desc = best_match(query, &task.desc);
+    let chars = desc
+        .as_ref()
+        .map(|found| found.matched_indices().copied().collect())
+        .unwrap_or_default();
+    let best = [Some(&task.notes), task.project.as_ref()]
+        .into_iter()
+        .flatten()
+        .chain(&task.tags)
+        .filter_map(|field| best_match(query, field))
+        .chain(desc)
+        .map(|found| found.score())
+        .max()?;
+    Some((best, chars))
+}
+
+/// Tasks fuzzy matching the query among the given indices of the list, best matches first
+///
+/// Tasks with the same score keep their order, and every task matches an
+/// empty query
+pub fn find(state: &State, rows: &[usize], query: &str) -> Vec<Match> {
+    let mut matches: Vec<Match> = rows
+        .iter()
+        .filter_map(|idx| {
+            let idx = *idx;
+            let task = &state.tasks.get(state.ids.get(idx)?)?.task;
+            if query.is_empty() {
+                return Some(Match {
+                    idx,
+                    score: 0,
+                    chars: Vec::new(),
+                });
+            }
+            let (score, chars) = score(query, task)?;
+            Some(Match { idx, score, chars })
+        })
+        .collect();
+    matches.sort_by_key(|found| std::cmp::Reverse(found.score));
+    matches
+}
+
+/// Indices of the chars of a description matching the query, empty if it does not match
+pub fn matched_chars(query: &str, desc: &str) -> Vec<usize> {
+    best_match(query, desc)
+        .map(|found| found.matched_indices().copied().collect())
+        .unwrap_or_default()
+}
+
+/// Index of the next task matching the query among the given indices of the list,
+/// going around at the end
+///
+/// The search starts after `from`, or before it when going backwards
+pub fn next_match(
+    state: &State,
+    rows: &[usize],
+    query: &str,
+    from: Option<usize>,
+    forward: bool,
+) -> Option<usize> {
+    let len = rows.len();
+    if len == 0 {
+        return None;
+    }
+    let start = match from.and_then(|from| rows.iter().position(|idx| *idx == from)) {
+        Some(from) => from,
+        // the first task is checked first in either direction
+        None if forward => len - 1,
+        None => 0,
+    };
+    (1..=len)
+        .map(|step| {
+            if forward {
+                (start + step) % len
+            } else {
+                (start + len - step % len) % len
+            }
+        })
+        .map(|row| rows[row])
+        .find(|idx| {
+            state
+                .ids
+                .get(*idx)
+                .and_then(|id| state.tasks.get(id))
+                .is_some_and(|item| score(query, &item.task).is_some())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_search() {
+        let mut state = State::new();
+        for desc in ["buy milk", "call mom", "fix the build", "bake bread"] {
+            state.add_task(desc);
+        }
+        // tasks are added at the top
+        assert_eq!(state.get_tasks()[0].desc, "bake bread");
+        let rows: Vec<usize> = (0..4).collect();
+
+        // both start a word with `bu`, so they keep their order
+        let found = find(&state, &rows, "bu");
+        assert_eq!(
+            found.iter().map(|found| found.idx).collect::<Vec<_>>(),
+            [1, 3]
+        );
+        let found = find(&state, &rows, "bm");
+        assert_eq!(found[0].idx, 3);
+        assert!(found.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert_eq!(find(&state, &rows, "").len(), 4);
+        assert!(find(&state, &rows, "xyz").is_empty());
+        assert_eq!(matched_chars("mk", "buy milk"), [4, 7]);
+
+        assert_eq!(next_match(&state, &rows, "b", None, true), Some(0));
+        assert_eq!(next_match(&state, &rows, "b", Some(0), true), Some(1));
+        assert_eq!(next_match(&state, &rows, "b", Some(3), true), Some(0));
+        assert_eq!(next_match(&state, &rows, "b", Some(0), false), Some(3));
+        assert_eq!(next_match(&state, &rows, "mom", Some(2), false), Some(2));
+        // only the given rows are searched
+        assert_eq!(next_match(&state, &[2, 3], "b", Some(2), true), Some(3));
+        assert_eq!(find(&state, &[0, 2], "bu").len(), 0);
+
+        // other fields match too, without highlighting the description
+        let task = Task {
+            desc: "call".to_string(),
+            tags: vec!["family".to_string()],
+            ..Default::default()
+        };
+        let (_, chars) = score("fam", &task).unwrap();
+        assert!(chars.is_empty());
+        assert_eq!(score("ca", &task).unwrap().1, [0, 1]);
+        assert!(score("work", &task).is_none());
+    }
+}
@@ -1,70 +1,89 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use chrono::{DateTime, Local};
-use tui_widget_list::Listable;
-
-use crate::{get_id, ui::render_list_item, Id};
+use crate::{
+    get_id,
+    history::{History, Operation, Step},
+    Id,
+};
+use chrono::{DateTime, Duration, Local};
 
 /// Structure of a single task
-#[derive(Default, serde::Serialize, serde::Deserialize)]
+#[derive(Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Task {
     pub id: Id,
     pub desc: String,
     pub completed: bool,
     pub last_updated: DateTime<Local>,
+    /// priority from `A` (highest) to `Z`
+    #[serde(default)]
+    pub priority: Option<char>,
+    #[serde(default)]
+    pub created: Option<DateTime<Local>>,
+    /// when the task was marked complete
+    #[serde(default)]
+    pub completed_on: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub due: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// longer free form details about the task
+    #[serde(default)]
+    pub notes: String,
+    /// ids of the tasks which must be completed before this one
+    #[serde(default)]
+    pub depends: Vec<Id>,
+    /// attributes from other tools which are not modelled, kept so that they are not lost
+    #[serde(default)]
+    pub extra: BTreeMap<String, String>,
 }
 
 impl Task {
-    fn new(task: &str) -> Self {
+    fn new(id: Id, task: &str) -> Self {
         Self {
-            id: get_id(),
+            id,
             desc: task.to_owned(),
-            completed: false,
             last_updated: Local::now(),
+            created: Some(Local::now()),
+            ..Default::default()
         }
     }
-    fn mark_complete(&mut self) {
+    pub(crate) fn mark_complete(&mut self) {
         self.completed = true;
+        self.completed_on = Some(Local::now());
     }
-    fn mark_incomplete(&mut self) {
+    pub(crate) fn mark_incomplete(&mut self) {
         self.completed = false;
+        self.completed_on = None;
     }
 }
 
-/// wrapper for a task as a list item
-pub struct ListItem {
+/// A deleted task waiting in the trash
+#[derive(Clone)]
+pub struct TrashedTask {
     pub task: Task,
-    pub selected: bool,
+    pub deleted: DateTime<Local>,
 }
 
-impl Listable for &ListItem {
-    fn height(&self) -> usize {
-        1
-    }
-    fn highlight(self) -> Self
-    where
-        Self: Sized,
-    {
-        self
-    }
+/// A task moved out of the active list
+#[derive(Clone)]
+pub struct ArchivedTask {
+    pub task: Task,
+    pub archived: DateTime<Local>,
 }
 
-impl ratatui::widgets::Widget for &ListItem {
-    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
-        render_list_item(self, area, buf);
-    }
+/// wrapper for a task as a list item
+pub struct ListItem {
+    pub task: Task,
+    pub selected: bool,
 }
 
 impl ListItem {
     pub fn from(task: &Task) -> Self {
         Self {
             selected: false,
-            task: Task {
-                completed: task.completed,
-                desc: task.desc.clone(),
-                id: task.id,
-                last_updated: task.last_updated,
-            },
+            task: task.clone(),
         }
     }
     fn set_selected(&mut self) {
@@ -79,8 +98,18 @@ impl ListItem {
 pub struct State {
     pub ids: Vec<Id>,
     pub tasks: HashMap<Id, ListItem>,
-    /// index of selected task
-    pub selected: Option<usize>,
+    /// index of the task under the cursor
+    pub cursor: Option<usize>,
+    /// tasks marked for the actions applying to several tasks at once
+    pub marked: HashSet<Id>,
+    /// changes which can be undone or redone
+    pub history: History,
+    /// removed tasks which can still be restored, most recent first
+    pub trash: Vec<TrashedTask>,
+    /// ids of the archived tasks, which are not given to new tasks
+    pub reserved: HashSet<Id>,
+    /// number of changes made to the tasks or the trash, to tell when they need saving
+    pub revision: usize,
 }
 
 impl State {
@@ -88,51 +117,298 @@ impl State {
         Self {
             ids: Vec::new(),
             tasks: HashMap::new(),
-            selected: None,
+            cursor: None,
+            marked: HashSet::new(),
+            history: History::new(),
+            trash: Vec::new(),
+            reserved: HashSet::new(),
+            revision: 0,
+        }
+    }
+
+    /// Build a state holding the given tasks in order
+    pub fn from_tasks(tasks: Vec<Task>) -> Self {
+        let mut state = Self::new();
+        for task in tasks {
+            state.ids.push(task.id);
+            state.tasks.insert(task.id, ListItem::from(&task));
         }
+        state
     }
 
     /// Move app state selection
     pub fn move_selection(&mut self, upwards: bool) {
-        if let Some(selected) = &self.selected {
-            let next = {
-                if upwards {
-                    selected.saturating_sub(1)
-                } else {
-                    *selected + 1
-                }
+        self.move_selection_by(if upwards { -1 } else { 1 });
+    }
+
+    /// Move the selection by a number of rows, staying inside the list
+    ///
+    /// The first task is selected if there was no selection
+    pub fn move_selection_by(&mut self, rows: isize) {
+        match self.cursor {
+            Some(selected) => self.select(selected.saturating_add_signed(rows)),
+            None => self.select(0),
+        }
+    }
+
+    /// Select the task at an index, the last task is selected if it is past the end
+    pub fn select(&mut self, idx: usize) {
+        if self.ids.is_empty() {
+            self.cursor = None;
+            return;
+        }
+        let next = idx.min(self.ids.len() - 1);
+        if let Some(item) = self
+            .cursor
+            .and_then(|selected| self.ids.get(selected))
+            .and_then(|id| self.tasks.get_mut(id))
+        {
+            item.set_unselected();
+        }
+        self.tasks.get_mut(&self.ids[next]).unwrap().set_selected();
+        self.cursor = Some(next);
+    }
+
+    /// Leave no task selected
+    pub fn deselect(&mut self) {
+        if let Some(item) = self
+            .cursor
+            .and_then(|selected| self.ids.get(selected))
+            .and_then(|id| self.tasks.get_mut(id))
+        {
+            item.set_unselected();
+        }
+        self.cursor = None;
+    }
+
+    /// Generate an id which is not used by any existing task, nor by a task in the trash or the archive
+    pub fn unused_id(&self) -> Id {
+        loop {
+            let id = get_id();
+            if !self.tasks.contains_key(&id)
+                && !self.reserved.contains(&id)
+                && self.trash.iter().all(|trashed| trashed.task.id != id)
+            {
+                return id;
             }
-            .clamp(0, self.ids.len() - 1);
-            self.tasks
-                .get_mut(&self.ids[*selected])
-                .unwrap()
-                .set_unselected();
-            self.tasks.get_mut(&self.ids[next]).unwrap().set_selected();
-            self.selected = Some(next);
-        } else {
-            self.tasks.get_mut(&self.ids[0]).unwrap().set_selected();
-            self.selected = Some(0);
         }
     }
 
     /// Add a new task to the given state
     pub fn add_task(&mut self, new_task: &str) {
-        let new_id = get_id();
-        self.ids.insert(0, new_id);
-        self.tasks
-            .insert(new_id, ListItem::from(&Task::new(new_task)));
+        let task = Task::new(self.unused_id(), new_task);
+        self.perform(vec![Operation::Insert { index: 0, task }]);
+    }
+
+    /// Add a task filled in elsewhere, like the form of the TUI
+    ///
+    /// It gets a new id and its dates are set to now
+    pub fn insert_task(&mut self, mut task: Task) {
+        task.id = self.unused_id();
+        task.created = Some(Local::now());
+        task.last_updated = Local::now();
+        self.perform(vec![Operation::Insert { index: 0, task }]);
+    }
+
+    /// Replace a task with a changed copy of it, nothing is done if it did not change
+    pub fn update_task(&mut self, mut task: Task) -> Option<()> {
+        let before = self.tasks.get(&task.id)?.task.clone();
+        if before == task {
+            return Some(());
+        }
+        task.last_updated = Local::now();
+        self.perform(vec![Operation::update(before, task)]);
+        Some(())
     }
 
     /// remove task with given id
     pub fn remove_task(&mut self, id: &Id) -> Option<()> {
-        if self.tasks.remove(id).is_some() {
-            self.ids.retain(|old_id| old_id != id);
-            Some(())
-        } else {
-            None
+        let index = self.ids.iter().position(|old_id| old_id == id)?;
+        let task = self.tasks.get(id)?.task.clone();
+        self.perform(vec![Operation::Trash { index, task }]);
+        Some(())
+    }
+
+    /// Move some tasks to the trash as a single step
+    ///
+    /// returns the number of tasks removed
+    pub fn remove_tasks(&mut self, ids: &[Id]) -> usize {
+        let mut removed: Vec<(usize, Id)> = ids
+            .iter()
+            .filter_map(|id| Some((self.ids.iter().position(|other| other == id)?, *id)))
+            .collect();
+        // later tasks go first so that the indices of the others stay right
+        removed.sort_by_key(|(index, _)| std::cmp::Reverse(*index));
+        removed.dedup();
+        let step: Step = removed
+            .into_iter()
+            .filter_map(|(index, id)| {
+                Some(Operation::Trash {
+                    index,
+                    task: self.tasks.get(&id)?.task.clone(),
+                })
+            })
+            .collect();
+        let count = step.len();
+        if count > 0 {
+            self.perform(step);
+        }
+        count
+    }
+
+    /// Change some tasks as a single step, the tasks which did not change are left out
+    ///
+    /// returns the number of tasks changed
+    pub fn update_tasks(&mut self, ids: &[Id], change: impl Fn(&mut Task)) -> usize {
+        let step: Step = ids
+            .iter()
+            .filter_map(|id| {
+                let before = self.tasks.get(id)?.task.clone();
+                let mut after = before.clone();
+                change(&mut after);
+                if after == before {
+                    return None;
+                }
+                after.last_updated = Local::now();
+                Some(Operation::update(before, after))
+            })
+            .collect();
+        let count = step.len();
+        if count > 0 {
+            self.perform(step);
+        }
+        count
+    }
+
+    /// Mark a task for the actions applying to several tasks, or unmark it
+    pub fn toggle_mark(&mut self, id: Id) {
+        if !self.marked.remove(&id) && self.tasks.contains_key(&id) {
+            self.marked.insert(id);
         }
     }
 
+    /// Tasks an action applies to, the marked ones in list order or else the one under the cursor
+    pub fn targets(&self) -> Vec<Id> {
+        if self.marked.is_empty() {
+            return self
+                .cursor
+                .and_then(|idx| self.ids.get(idx))
+                .copied()
+                .into_iter()
+                .collect();
+        }
+        self.ids
+            .iter()
+            .filter(|id| self.marked.contains(id))
+            .copied()
+            .collect()
+    }
+
+    /// move every task to the trash as a single step
+    pub fn trash_all(&mut self) -> usize {
+        let step: Step = self
+            .get_tasks()
+            .into_iter()
+            .map(|task| Operation::Trash {
+                index: 0,
+                task: task.clone(),
+            })
+            .collect();
+        let count = step.len();
+        self.perform(step);
+        count
+    }
+
+    /// bring a task back from the trash to the top of the list
+    ///
+    /// It gets a new id if a task of the list has taken its id meanwhile
+    pub fn restore_task(&mut self, id: &Id) -> Option<()> {
+        let idx = self
+            .trash
+            .iter()
+            .position(|trashed| trashed.task.id == *id)?;
+        let mut task = self.trash[idx].task.clone();
+        if self.tasks.contains_key(id) {
+            self.trash.remove(idx);
+            task.id = self.unused_id();
+        }
+        self.perform(vec![Operation::Restore { index: 0, task }]);
+        Some(())
+    }
+
+    /// permanently delete a task from the trash
+    pub fn purge_task(&mut self, id: &Id) -> Option<()> {
+        let idx = self
+            .trash
+            .iter()
+            .position(|trashed| trashed.task.id == *id)?;
+        self.trash.remove(idx);
+        self.revision += 1;
+        Some(())
+    }
+
+    /// permanently delete tasks which have been in the trash for longer than `age`
+    ///
+    /// returns the number of tasks deleted
+    pub fn purge_trash(&mut self, age: Duration) -> usize {
+        let cutoff = Local::now() - age;
+        let before = self.trash.len();
+        self.trash.retain(|trashed| trashed.deleted > cutoff);
+        let purged = before - self.trash.len();
+        if purged > 0 {
+            self.revision += 1;
+        }
+        purged
+    }
+
+    /// The operations needed to turn the current tasks into the given ones
+    pub fn changes_to(&self, tasks: &[Task]) -> Step {
+        // later tasks go first so that the indices of the others stay right
+        let mut step: Step = self
+            .get_tasks()
+            .into_iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, task)| tasks.iter().all(|other| other.id != task.id))
+            .map(|(index, task)| Operation::Remove {
+                index,
+                task: task.clone(),
+            })
+            .collect();
+        for (index, task) in tasks.iter().enumerate() {
+            match self.tasks.get(&task.id) {
+                None => step.push(Operation::Insert {
+                    index,
+                    task: task.clone(),
+                }),
+                Some(list_item) if list_item.task != *task => {
+                    step.push(Operation::update(list_item.task.clone(), task.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        step
+    }
+
+    /// Apply a step of operations as a single change which can be undone
+    pub fn apply_changes(&mut self, step: Step) {
+        self.perform(step);
+    }
+
+    /// take tasks out of the state without recording them in history or trash
+    ///
+    /// used when tasks are moved to another store, like the archive
+    pub fn detach_tasks(&mut self, ids: &[Id]) -> Vec<Task> {
+        let detached = ids
+            .iter()
+            .filter_map(|id| self.tasks.remove(id))
+            .map(|list_item| list_item.task)
+            .collect();
+        self.ids.retain(|id| self.tasks.contains_key(id));
+        self.fix_selection();
+        detached
+    }
+
     /// delete a particular task at an index from the given state
     pub fn remove_task_by_seq(&mut self, idx: usize) {
         if idx >= self.tasks.len() {
@@ -141,6 +417,16 @@ impl State {
         self.remove_task(&self.ids[idx].clone());
     }
 
+    /// change the description of task with given id
+    pub fn edit_task(&mut self, id: Id, desc: &str) -> Option<()> {
+        let before = self.tasks.get(&id)?.task.clone();
+        let mut after = before.clone();
+        after.desc = desc.to_owned();
+        after.last_updated = Local::now();
+        self.perform(vec![Operation::update(before, after)]);
+        Some(())
+    }
+
     /// mark incomplete task complete and vice versa
     ///
     /// returns true if task marked as complete else false
@@ -153,16 +439,129 @@ impl State {
     ///
     /// returns true if task marked as complete else false
     pub fn toggle_task_status_by_id(&mut self, id: Id) -> Option<bool> {
-        if let Some(list_item) = self.tasks.get_mut(&id) {
-            if list_item.task.completed {
-                list_item.task.mark_incomplete();
-                Some(false)
+        let before = self.tasks.get(&id)?.task.clone();
+        let mut after = before.clone();
+        if after.completed {
+            after.mark_incomplete();
+        } else {
+            after.mark_complete();
+        }
+        after.last_updated = Local::now();
+        let completed = after.completed;
+        self.perform(vec![Operation::update(before, after)]);
+        Some(completed)
+    }
+
+    /// Revert the last recorded step
+    ///
+    /// returns the step which was reverted, if any
+    pub fn undo(&mut self) -> Option<Step> {
+        let step = self.history.undo.pop()?;
+        for op in step.iter().rev() {
+            self.apply(&op.inverse());
+        }
+        self.fix_selection();
+        self.revision += 1;
+        self.history.redo.push(step.clone());
+        Some(step)
+    }
+
+    /// Apply the last reverted step again
+    ///
+    /// returns the step which was applied, if any
+    pub fn redo(&mut self) -> Option<Step> {
+        let step = self.history.redo.pop()?;
+        for op in &step {
+            self.apply(op);
+        }
+        self.fix_selection();
+        self.revision += 1;
+        self.history.undo.push(step.clone());
+        Some(step)
+    }
+
+    /// Apply a step of operations and record it in history
+    fn perform(&mut self, step: Step) {
+        for op in &step {
+            self.apply(op);
+        }
+        self.fix_selection();
+        if !step.is_empty() {
+            self.revision += 1;
+        }
+        self.history.record(step);
+    }
+
+    /// Apply changes made somewhere else, like in the data file
+    ///
+    /// They are neither recorded in history, so that undo only reverts changes
+    /// made here, nor counted as changes needing to be saved
+    pub fn apply_unrecorded(&mut self, step: &[Operation]) {
+        for op in step {
+            self.apply(op);
+        }
+        self.fix_selection();
+    }
+
+    /// Apply a single operation without recording it
+    fn apply(&mut self, op: &Operation) {
+        match op {
+            Operation::Insert { index, task } => {
+                self.ids.insert((*index).min(self.ids.len()), task.id);
+                self.tasks.insert(task.id, ListItem::from(task));
+            }
+            Operation::Remove { task, .. } => {
+                self.tasks.remove(&task.id);
+                self.ids.retain(|old_id| *old_id != task.id);
+            }
+            Operation::Trash { task, .. } => {
+                if self.tasks.remove(&task.id).is_some() {
+                    self.ids.retain(|old_id| *old_id != task.id);
+                    self.trash.insert(
+                        0,
+                        TrashedTask {
+                            task: task.clone(),
+                            deleted: Local::now(),
+                        },
+                    );
+                }
+            }
+            Operation::Restore { index, task } => {
+                self.trash.retain(|trashed| trashed.task.id != task.id);
+                self.ids.insert((*index).min(self.ids.len()), task.id);
+                self.tasks.insert(task.id, ListItem::from(task));
+            }
+            Operation::Update { after, .. } => {
+                if let Some(list_item) = self.tasks.get_mut(&after.id) {
+                    list_item.task = after.as_ref().clone();
+                }
+            }
+        }
+    }
+
+    /// Keep the selection inside the list after tasks are added or removed
+    fn fix_selection(&mut self) {
+        let tasks = &self.tasks;
+        self.marked.retain(|id| tasks.contains_key(id));
+        if let Some(selected) = self.cursor {
+            if let Some(id) = self.ids.get(selected) {
+                if self.tasks.get(id).is_some_and(|item| item.selected) {
+                    return;
+                }
+            }
+            for list_item in self.tasks.values_mut() {
+                list_item.set_unselected();
+            }
+            if self.ids.is_empty() {
+                self.cursor = None;
             } else {
-                list_item.task.mark_complete();
-                Some(true)
+                let selected = selected.min(self.ids.len() - 1);
+                self.tasks
+                    .get_mut(&self.ids[selected])
+                    .unwrap()
+                    .set_selected();
+                self.cursor = Some(selected);
             }
-        } else {
-            None
         }
     }
 
@@ -192,12 +591,163 @@ mod tests {
         state.add_task("abc");
         state.add_task("123");
         state.add_task("xyz");
-        assert!(state.selected.is_none());
+        assert!(state.cursor.is_none());
         state.move_selection(true);
-        assert!(state.selected.is_some());
-        assert_eq!(state.selected.unwrap(), 0);
+        assert!(state.cursor.is_some());
+        assert_eq!(state.cursor.unwrap(), 0);
         state.move_selection(false);
-        assert_eq!(state.selected.unwrap(), 1);
+        assert_eq!(state.cursor.unwrap(), 1);
         assert!(state.tasks.get(&state.ids[1]).unwrap().selected);
     }
+
+    #[test]
+    fn check_selection_by_page() {
+        let mut state = State::new();
+        state.add_task("abc");
+        state.add_task("123");
+        state.add_task("xyz");
+        state.move_selection(false);
+        state.move_selection_by(10);
+        assert_eq!(state.cursor.unwrap(), 2);
+        assert!(!state.tasks.get(&state.ids[0]).unwrap().selected);
+        state.move_selection_by(-10);
+        assert_eq!(state.cursor.unwrap(), 0);
+
+        let mut empty = State::new();
+        empty.move_selection(false);
+        empty.select(usize::MAX);
+        assert!(empty.cursor.is_none());
+    }
+
+    #[test]
+    fn check_batch() {
+        let mut state = State::new();
+        for desc in ["a", "b", "c", "d"] {
+            state.add_task(desc);
+        }
+        state.select(3);
+        assert_eq!(state.targets(), [state.ids[3]]);
+        let (first, third) = (state.ids[0], state.ids[2]);
+        state.toggle_mark(third);
+        state.toggle_mark(first);
+        assert_eq!(state.targets(), [first, third]);
+
+        assert_eq!(
+            state.update_tasks(&state.targets(), |task| task.priority = Some('A')),
+            2
+        );
+        assert_eq!(
+            state.update_tasks(&state.targets(), |task| task.priority = Some('A')),
+            0
+        );
+        assert_eq!(state.remove_tasks(&state.targets()), 2);
+        assert!(state.marked.is_empty());
+        assert_eq!(state.get_tasks().len(), 2);
+        // a batch is undone at once, back in place
+        state.undo();
+        assert_eq!(state.ids[0], first);
+        assert_eq!(state.ids[2], third);
+        state.undo();
+        assert!(state.get_tasks().iter().all(|task| task.priority.is_none()));
+    }
+
+    #[test]
+    fn check_undo_redo() {
+        let mut state = State::new();
+        state.add_task("abc");
+        state.add_task("xyz");
+        let id = state.ids[1];
+        state.toggle_task_status_by_id(id);
+        state.edit_task(id, "def");
+        state.remove_task(&id);
+        assert_eq!(state.ids.len(), 1);
+
+        state.undo();
+        assert_eq!(state.ids[1], id);
+        assert_eq!(state.tasks.get(&id).unwrap().task.desc, "def");
+        state.undo();
+        assert_eq!(state.tasks.get(&id).unwrap().task.desc, "abc");
+        state.undo();
+        assert!(!state.tasks.get(&id).unwrap().task.completed);
+
+        state.redo();
+        assert!(state.tasks.get(&id).unwrap().task.completed);
+        state.add_task("new");
+        assert!(state.redo().is_none());
+    }
+
+    #[test]
+    fn check_trash() {
+        let mut state = State::new();
+        state.add_task("abc");
+        state.add_task("xyz");
+        let id = state.ids[0];
+        state.remove_task(&id);
+        assert_eq!(state.trash.len(), 1);
+        assert_eq!(state.purge_trash(Duration::days(1)), 0);
+
+        state.restore_task(&id);
+        assert!(state.trash.is_empty());
+        assert_eq!(state.ids[0], id);
+        state.undo();
+        assert_eq!(state.trash.len(), 1);
+        state.redo();
+        assert!(state.trash.is_empty());
+
+        // undoing an add or applying an outside removal does not fill the trash
+        state.add_task("undone");
+        state.undo();
+        let task = state.get_tasks()[0].clone();
+        let removal = Operation::Remove { index: 0, task };
+        state.apply_unrecorded(std::slice::from_ref(&removal));
+        assert!(state.trash.is_empty());
+        state.apply_unrecorded(&[removal.inverse()]);
+
+        assert_eq!(state.trash_all(), 2);
+        assert!(state.ids.is_empty());
+        state.undo();
+        assert_eq!(state.ids.len(), 2);
+        assert!(state.trash.is_empty());
+
+        state.remove_task(&id);
+        assert_eq!(state.purge_trash(Duration::zero()), 1);
+    }
+
+    #[test]
+    fn check_restore_taken_id() {
+        let mut state = State::new();
+        state.add_task("trashed");
+        let id = state.ids[0];
+        state.remove_task(&id);
+        assert_ne!(state.unused_id(), id);
+
+        // a task of the list with the same id, as in data written before ids were checked
+        let live = Task::new(id, "live");
+        state.ids.push(id);
+        state.tasks.insert(id, ListItem::from(&live));
+        state.restore_task(&id);
+        assert!(state.trash.is_empty());
+        assert_eq!(state.ids.len(), 2);
+        assert_ne!(state.ids[0], id);
+        assert_eq!(state.tasks[&state.ids[0]].task.desc, "trashed");
+        assert_eq!(state.tasks[&id].task.desc, "live");
+    }
+
+    #[test]
+    fn check_changes_to() {
+        let mut state = State::new();
+        state.add_task("abc");
+        state.add_task("xyz");
+        let snapshot: Vec<Task> = state.get_tasks().into_iter().cloned().collect();
+        state.toggle_task_status(0);
+        state.remove_task_by_seq(1);
+        state.add_task("new");
+
+        let step = state.changes_to(&snapshot);
+        assert_eq!(step.len(), 3);
+        state.apply_changes(step);
+        let descs: Vec<&str> = state.get_tasks().iter().map(|t| t.desc.as_str()).collect();
+        assert_eq!(descs, ["xyz", "abc"]);
+        assert!(!state.get_tasks()[0].completed);
+    }
 }
@@ -0,0 +1,671 @@
+use std::{collections::HashSet, fs, process::Command};
+
+use chrono::{DateTime, Local};
+
+use crate::{
+    crypto,
+    files::{
+        data_dir, data_file, no_metadata, tasks_from_csv, tasks_to_csv, ARCHIVE_NAME, CSV_NAME,
+        TRASH_NAME,
+    },
+    oplog::{self, OPS_FOLDER},
+    state::Task,
+    Id, Result,
+};
+
+/// Files which only make sense on the device they were written on
+const IGNORED: &str =
+    "backups/\nhistory.csv\nrecovery.csv\ndevice\nconfig.toml\nkeymap.toml\nthemes/\n";
+
+/// What happened during a sync
+#[derive(Default)]
+pub struct SyncReport {
+    /// local changes were committed
+    pub committed: bool,
+    /// changes from the remote were applied
+    pub pulled: bool,
+    /// both sides had changes which had to be merged
+    pub merged: bool,
+    /// local commits were sent to the remote
+    pub pushed: bool,
+    /// no remote is configured, so only local history is kept
+    pub local_only: bool,
+}
+
+/// A version of a task found in git history
+pub struct LogEntry {
+    pub commit: String,
+    pub date: DateTime<Local>,
+    pub message: String,
+    /// what happened to the task in this commit
+    pub change: String,
+    /// the task after the commit, none if it was removed
+    pub task: Option<Task>,
+}
+
+fn git_command(args: &[&str]) -> Result<std::process::Output> {
+    let dir = data_dir().ok_or_else(no_metadata)?;
+    Ok(Command::new("git").arg("-C").arg(dir).args(args).output()?)
+}
+
+/// Run git inside the storage directory and return its output
+fn git(args: &[&str]) -> Result<String> {
+    let output = git_command(args)?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string())
+}
+
+/// Run git only to know whether it succeeds
+fn git_succeeds(args: &[&str]) -> Result<bool> {
+    Ok(git_command(args)?.status.success())
+}
+
+/// Contents of a file at some revision as stored, empty if it did not exist
+fn git_show_stored(rev: &str, file: &str) -> Result<Vec<u8>> {
+    let output = git_command(&["show", &format!("{rev}:{file}")])?;
+    Ok(if output.status.success() {
+        output.stdout
+    } else {
+        Vec::new()
+    })
+}
+
+/// Contents of a file at some revision, empty if it did not exist
+fn git_show(rev: &str, file: &str) -> Result<Vec<u8>> {
+    crypto::open(git_show_stored(rev, file)?)
+}
+
+/// Contents of a file at a revision which may predate the encryption
+fn git_show_earlier(rev: &str, file: &str) -> Result<Vec<u8>> {
+    crypto::open_earlier(git_show_stored(rev, file)?)
+}
+
+/// Paths of the files in a folder at some revision
+fn git_files(rev: &str, folder: &str) -> Result<Vec<String>> {
+    let files = git(&["ls-tree", "--name-only", rev, &format!("{folder}/")])?;
+    Ok(files.lines().map(String::from).collect())
+}
+
+/// Initialize the repository on first use
+fn ensure_repo() -> Result<()> {
+    let dir = data_dir().ok_or_else(no_metadata)?;
+    if !dir.join(".git").exists() {
+        git(&["init", "-q"])?;
+        fs::write(dir.join(".gitignore"), IGNORED)?;
+    }
+    Ok(())
+}
+
+/// Commit everything in the storage directory, returns false if nothing changed
+fn commit(message: &str) -> Result<bool> {
+    git(&["add", "-A"])?;
+    if git(&["status", "--porcelain"])?.is_empty() && !is_merging()? {
+        return Ok(false);
+    }
+    git_as_author(&["commit", "-q", "-m", message])?;
+    Ok(true)
+}
+
+/// Run a git command which creates commits
+///
+/// Falls back to a generic identity if the user has not configured one
+fn git_as_author(args: &[&str]) -> Result<String> {
+    if git_succeeds(&["config", "user.email"])? {
+        return git(args);
+    }
+    let mut with_identity = vec![
+        "-c",
+        "user.name=todo-cli",
+        "-c",
+        "user.email=todo-cli@localhost",
+    ];
+    with_identity.extend(args);
+    git(&with_identity)
+}
+
+fn is_merging() -> Result<bool> {
+    git_succeeds(&["rev-parse", "-q", "--verify", "MERGE_HEAD"])
+}
+
+/// Use the given url or path as the remote to sync with
+fn set_remote(remote: &str) -> Result<()> {
+    if git_succeeds(&["remote", "get-url", "origin"])? {
+        git(&["remote", "set-url", "origin", remote])?;
+    } else {
+        git(&["remote", "add", "origin", remote])?;
+    }
+    Ok(())
+}
+
+/// Commit the tasks and exchange them with the configured remote
+///
+/// When both sides changed, tasks are merged field by field and the side
+/// updated last wins a field changed on both sides
+pub fn sync(remote: Option<&str>) -> Result<SyncReport> {
+    let mut report = SyncReport::default();
+    ensure_repo()?;
+    if let Some(remote) = remote {
+        set_remote(remote)?;
+    }
+    report.committed = commit("Update tasks")?;
+    if !git_succeeds(&["remote", "get-url", "origin"])? {
+        report.local_only = true;
+        return Ok(report);
+    }
+
+    let branch = git(&["symbolic-ref", "--short", "HEAD"])?;
+    let remote_ref = format!("origin/{branch}");
+    git(&["fetch", "-q", "origin"])?;
+    if git_succeeds(&["rev-parse", "-q", "--verify", &remote_ref])? {
+        if git_succeeds(&["merge-base", "--is-ancestor", "HEAD", &remote_ref])? {
+            report.pulled = git(&["rev-parse", "HEAD"])? != git(&["rev-parse", &remote_ref])?;
+            git(&["merge", "-q", "--ff-only", &remote_ref])?;
+        } else if !git_succeeds(&["merge-base", "--is-ancestor", &remote_ref, "HEAD"])? {
+            merge(&remote_ref)?;
+            report.pulled = true;
+            report.merged = true;
+        }
+    }
+    if git(&["rev-parse", "HEAD"])? != git(&["rev-parse", &remote_ref]).unwrap_or_default() {
+        git(&["push", "-q", "-u", "origin", &branch])?;
+        report.pushed = true;
+    }
+    Ok(report)
+}
+
+/// Merge diverged histories, resolving the tasks ourselves
+fn merge(remote_ref: &str) -> Result<()> {
+    let base = git(&["merge-base", "HEAD", remote_ref]).ok();
+    let versions = |file: &str| -> Result<[Vec<u8>; 3]> {
+        Ok([
+            match &base {
+                Some(base) => git_show_earlier(base, file)?,
+                None => Vec::new(),
+            },
+            git_show("HEAD", file)?,
+            git_show(remote_ref, file)?,
+        ])
+    };
+
+    // in oplog mode the tasks come from the logs of every device
+    let (tasks, logs) = if oplog::enabled() {
+        let mut files = git_files("HEAD", OPS_FOLDER)?;
+        files.extend(git_files(remote_ref, OPS_FOLDER)?);
+        files.sort();
+        files.dedup();
+        let mut logs = Vec::new();
+        for file in files.into_iter().filter(|file| file.ends_with(".csv")) {
+            let versions = versions(&file)?;
+            logs.push((file, versions));
+        }
+        merge_logs(logs, &oplog::device()?)?
+    } else {
+        let [base_data, ours, theirs] = versions(CSV_NAME)?;
+        let tasks = merge_tasks(
+            &tasks_from_csv(&base_data)?,
+            &tasks_from_csv(&ours)?,
+            &tasks_from_csv(&theirs)?,
+        );
+        (tasks, Vec::new())
+    };
+    let live: HashSet<String> = tasks.iter().map(|task| task.id.to_string()).collect();
+    let [base_trash, ours_trash, theirs_trash] = versions(TRASH_NAME)?;
+    let trash = merge_rows(&base_trash, &ours_trash, &theirs_trash, &live)?;
+    let [_, ours_archive, theirs_archive] = versions(ARCHIVE_NAME)?;
+    let archive = union_rows(&ours_archive, &theirs_archive, &HashSet::new())?;
+
+    // record the merge, contents are written by us
+    git_as_author(&[
+        "merge",
+        "-q",
+        "--no-commit",
+        "--no-ff",
+        "--allow-unrelated-histories",
+        "-s",
+        "ours",
+        remote_ref,
+    ])?;
+    let write = |file: &str, contents: Vec<u8>| -> Result<()> {
+        if !contents.is_empty() {
+            crypto::write(&data_file(file).ok_or_else(no_metadata)?, &contents)?;
+        }
+        Ok(())
+    };
+    let mut merged: HashSet<String> = [CSV_NAME, TRASH_NAME, ARCHIVE_NAME]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    write(CSV_NAME, tasks_to_csv(&tasks)?)?;
+    for (file, log) in logs {
+        write(&file, log)?;
+        merged.insert(file);
+    }
+    write(TRASH_NAME, trash)?;
+    write(ARCHIVE_NAME, archive)?;
+    take_their_changes(base.as_deref(), remote_ref, &merged)?;
+    commit(&format!("Merge tasks from {remote_ref}"))?;
+    Ok(())
+}
+
+/// Bring in the changes they made to the tracked files which are not in `merged`
+///
+/// The merge keeps our tree, so a file only they changed since the base is
+/// taken from their side, removals included. Files changed on both sides
+/// keep our version.
+fn take_their_changes(
+    base: Option<&str>,
+    remote_ref: &str,
+    merged: &HashSet<String>,
+) -> Result<()> {
+    let mut files: Vec<String> = Vec::new();
+    for rev in ["HEAD", remote_ref] {
+        files.extend(
+            git(&["ls-tree", "-r", "--name-only", rev])?
+                .lines()
+                .map(String::from),
+        );
+    }
+    files.sort();
+    files.dedup();
+    for file in files.iter().filter(|file| !merged.contains(*file)) {
+        let exists = |rev: &str| git_succeeds(&["cat-file", "-e", &format!("{rev}:{file}")]);
+        let version = |rev: &str| -> Result<Option<Vec<u8>>> {
+            Ok(if exists(rev)? {
+                Some(git_show_stored(rev, file)?)
+            } else {
+                None
+            })
+        };
+        let base_version = match base {
+            Some(base) => version(base)?,
+            None => None,
+        };
+        let theirs = version(remote_ref)?;
+        if version("HEAD")? != base_version || theirs == base_version {
+            continue;
+        }
+        if theirs.is_some() {
+            git(&["checkout", remote_ref, "--", file])?;
+        } else {
+            git(&["rm", "-q", "--", file])?;
+        }
+    }
+    Ok(())
+}
+
+/// Path of a file in the storage directory along with its contents
+type File = (String, Vec<u8>);
+
+/// Merge the logs of every device, given as their versions at the merge base,
+/// here and on the remote
+///
+/// Each log keeps the operations of both of its versions, as either side may
+/// have been compacted or synced with other devices. Tasks this device added
+/// under an id the other side used for another task get a new id first.
+/// Returns the tasks the logs hold along with the new contents of the logs
+fn merge_logs(logs: Vec<(String, [Vec<u8>; 3])>, device: &str) -> Result<(Vec<Task>, Vec<File>)> {
+    let own_log = format!("{OPS_FOLDER}/{device}.csv");
+    let mut base = Vec::new();
+    let mut theirs = Vec::new();
+    for (_, [base_log, _, theirs_log]) in &logs {
+        base.extend(oplog::ops_from_csv(base_log));
+        theirs.extend(oplog::ops_from_csv(theirs_log));
+    }
+    let mut all = Vec::new();
+    let mut merged = Vec::new();
+    for (file, [_, ours_log, theirs_log]) in logs {
+        let mut ours = oplog::ops_from_csv(&ours_log);
+        if file == own_log {
+            oplog::separate_ids(&base, &mut ours, &theirs);
+        }
+        let ops = oplog::union(ours, oplog::ops_from_csv(&theirs_log));
+        merged.push((file, oplog::ops_to_csv(&ops)?));
+        all.extend(ops);
+    }
+    Ok((oplog::materialize(&all)?, merged))
+}
+
+/// Three way merge of tasks
+///
+/// Tasks added on either side are kept, tasks removed on one side are
+/// removed unless the other side changed them. For tasks changed on both
+/// sides each field is merged on its own and when both sides changed the
+/// same field, the side which updated the task last wins.
+pub fn merge_tasks(base: &[Task], ours: &[Task], theirs: &[Task]) -> Vec<Task> {
+    let find = |tasks: &'_ [Task], id: Id| tasks.iter().find(|task| task.id == id).cloned();
+    let mut merged = Vec::new();
+    // tasks only they have come first, like new tasks are added on top
+    for task in theirs {
+        if find(ours, task.id).is_none() && find(base, task.id).is_none_or(|base| base != *task) {
+            merged.push(task.clone());
+        }
+    }
+    for task in ours {
+        match (find(theirs, task.id), find(base, task.id)) {
+            (Some(other), base) => merged.push(merge_task(base.as_ref(), task, &other)),
+            (None, Some(base)) if base == *task => {}
+            (None, _) => merged.push(task.clone()),
+        }
+    }
+    merged
+}
+
+fn merge_task(base: Option<&Task>, ours: &Task, theirs: &Task) -> Task {
+    let ours_newer = ours.last_updated >= theirs.last_updated;
+    let mut merged = if ours_newer {
+        ours.clone()
+    } else {
+        theirs.clone()
+    };
+    // true if the field should be taken from their side
+    let take_theirs = |ours_same: bool, theirs_same: bool| match base {
+        Some(_) if ours_same => true,
+        Some(_) if theirs_same => false,
+        _ => !ours_newer,
+    };
+    macro_rules! merge_field {
+        ($($field:ident),*) => {
+            $(
+                let from_theirs = take_theirs(
+                    base.is_some_and(|base| base.$field == ours.$field),
+                    base.is_some_and(|base| base.$field == theirs.$field),
+                );
+                merged.$field = if from_theirs {
+                    theirs.$field.clone()
+                } else {
+                    ours.$field.clone()
+                };
+            )*
+        };
+    }
+    merge_field!(desc, priority, created, due, project, tags, notes, depends, extra);
+
+    // status and completion time go together
+    let status = |task: &Task| (task.completed, task.completed_on);
+    let from_theirs = take_theirs(
+        base.is_some_and(|base| status(base) == status(ours)),
+        base.is_some_and(|base| status(base) == status(theirs)),
+    );
+    (merged.completed, merged.completed_on) = status(if from_theirs { theirs } else { ours });
+    merged.last_updated = ours.last_updated.max(theirs.last_updated);
+    merged
+}
+
+/// Union of the rows of two csv files, rows are matched by the id in the second column
+///
+/// Rows for ids in `skip` are left out
+fn union_rows(ours: &[u8], theirs: &[u8], skip: &HashSet<String>) -> Result<Vec<u8>> {
+    let mut header = None;
+    let mut seen = HashSet::new();
+    let mut rows = Vec::new();
+    for data in [ours, theirs] {
+        let mut reader = csv::ReaderBuilder::new().from_reader(data);
+        if header.is_none() && !data.is_empty() {
+            header = Some(reader.headers()?.clone());
+        }
+        for record in reader.records() {
+            let record = record?;
+            let id = record.get(1).unwrap_or_default().to_string();
+            if !skip.contains(&id) && seen.insert(id) {
+                rows.push(record);
+            }
+        }
+    }
+    let Some(header) = header else {
+        return Ok(Vec::new());
+    };
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    writer.write_record(&header)?;
+    for row in rows {
+        writer.write_record(&row)?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// Three way merge of the rows of csv files, rows are matched by the id in the second column
+///
+/// Rows found on both sides are taken from ours. A row only one side has is
+/// kept if it is new, and left out if the other side removed it since the
+/// base, like a task purged from the trash. Rows for ids in `skip` are left out
+fn merge_rows(base: &[u8], ours: &[u8], theirs: &[u8], skip: &HashSet<String>) -> Result<Vec<u8>> {
+    let ids = |data: &[u8]| -> Result<HashSet<String>> {
+        let mut reader = csv::ReaderBuilder::new().from_reader(data);
+        let mut ids = HashSet::new();
+        for record in reader.records() {
+            ids.insert(record?.get(1).unwrap_or_default().to_string());
+        }
+        Ok(ids)
+    };
+    let base = ids(base)?;
+    let (ours_ids, theirs_ids) = (ids(ours)?, ids(theirs)?);
+    let removed: HashSet<String> = base
+        .iter()
+        .filter(|id| !ours_ids.contains(*id) || !theirs_ids.contains(*id))
+        .cloned()
+        .collect();
+    union_rows(ours, theirs, &skip.union(&removed).cloned().collect())
+}
+
+/// Names of the fields which differ between two versions of a task
+fn changed_fields(before: &Task, after: &Task) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    let fields = [
+        ("description", before.desc != after.desc),
+        ("status", before.completed != after.completed),
+        ("priority", before.priority != after.priority),
+        ("due", before.due != after.due),
+        ("project", before.project != after.project),
+        ("tags", before.tags != after.tags),
+        ("notes", before.notes != after.notes),
+        ("dependencies", before.depends != after.depends),
+    ];
+    for (name, differs) in fields {
+        if differs {
+            changed.push(name);
+        }
+    }
+    changed
+}
+
+/// History of a task from the commits made while syncing, oldest first
+pub fn task_log(id: Id) -> Result<Vec<LogEntry>> {
+    let dir = data_dir().ok_or_else(no_metadata)?;
+    if !dir.join(".git").exists() {
+        return Ok(Vec::new());
+    }
+    let commits = git(&[
+        "log",
+        "--reverse",
+        "--first-parent",
+        "--format=%h%x09%aI%x09%s",
+        "--",
+        CSV_NAME,
+    ])?;
+    let mut log = Vec::new();
+    let mut previous: Option<Task> = None;
+    for line in commits.lines() {
+        let mut parts = line.splitn(3, '\t');
+        let (Some(commit), Some(date), Some(message)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let task = tasks_from_csv(&git_show_earlier(commit, CSV_NAME)?)?
+            .into_iter()
+            .find(|task| task.id == id);
+        let change = match (&previous, &task) {
+            (None, None) => continue,
+            (None, Some(_)) => "added".to_string(),
+            (Some(_), None) => "removed".to_string(),
+            (Some(before), Some(after)) => {
+                let changed = changed_fields(before, after);
+                if changed.is_empty() {
+                    continue;
+                }
+                format!("changed {}", changed.join(", "))
+            }
+        };
+        log.push(LogEntry {
+            commit: commit.to_string(),
+            date: DateTime::parse_from_rfc3339(date)?.with_timezone(&Local),
+            message: message.to_string(),
+            change,
+            task: task.clone(),
+        });
+        previous = task;
+    }
+    Ok(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn task(id: Id, desc: &str) -> Task {
+        Task {
+            id,
+            desc: desc.to_string(),
+            last_updated: Local::now() - Duration::hours(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn check_merge_tasks() {
+        let base = vec![task(1, "one"), task(2, "two"), task(3, "three")];
+
+        let mut ours = base.clone();
+        ours[0].desc = "one edited".to_string();
+        ours[0].last_updated = Local::now();
+        ours.remove(2);
+        ours.push(task(4, "ours"));
+
+        let mut theirs = base.clone();
+        theirs[0].completed = true;
+        theirs[1].desc = "two edited".to_string();
+        theirs[1].project = Some("home".to_string());
+        theirs.push(task(5, "theirs"));
+
+        let merged = merge_tasks(&base, &ours, &theirs);
+        let ids: Vec<Id> = merged.iter().map(|task| task.id).collect();
+        assert_eq!(ids, [5, 1, 2, 4]);
+        // both fields changed on different sides are kept
+        assert_eq!(merged[1].desc, "one edited");
+        assert!(merged[1].completed);
+        assert_eq!(merged[2].desc, "two edited");
+        assert_eq!(merged[2].project.as_deref(), Some("home"));
+
+        // the same field changed on both sides, the last writer wins
+        let mut theirs = base.clone();
+        theirs[0].desc = "one by them".to_string();
+        theirs[0].last_updated = Local::now() + Duration::minutes(1);
+        let merged = merge_tasks(&base, &ours, &theirs);
+        assert_eq!(merged[0].desc, "one by them");
+    }
+
+    #[test]
+    fn check_merge_rows() {
+        let rows = |ids: &[&str]| {
+            let mut data = "deleted,id,desc\n".to_string();
+            for id in ids {
+                data.push_str(&format!("now,{id},task {id}\n"));
+            }
+            data.into_bytes()
+        };
+        let ids = |data: &[u8]| -> Vec<String> {
+            csv::ReaderBuilder::new()
+                .from_reader(data)
+                .records()
+                .map(|record| record.unwrap()[1].to_string())
+                .collect()
+        };
+        // 1 is purged by them, 2 is kept, 3 and 4 are deleted on either side
+        // and 5 was restored by us
+        let base = rows(&["1", "2", "5"]);
+        let ours = rows(&["3", "1", "2"]);
+        let theirs = rows(&["4", "2", "5"]);
+        let merged = merge_rows(&base, &ours, &theirs, &HashSet::new()).unwrap();
+        assert_eq!(ids(&merged), ["3", "2", "4"]);
+
+        let live = HashSet::from(["3".to_string()]);
+        let merged = merge_rows(&base, &ours, &theirs, &live).unwrap();
+        assert_eq!(ids(&merged), ["2", "4"]);
+    }
+
+    /// Operations made on a device, clocks only need to keep growing
+    fn changes(millis: &mut i64, device: &str, before: &[Task], after: &[Task]) -> Vec<oplog::Op> {
+        oplog::diff(before, after, || {
+            *millis += 1;
+            oplog::Clock {
+                millis: *millis,
+                counter: 0,
+                device: device.to_string(),
+            }
+        })
+    }
+
+    #[test]
+    fn check_merge_logs() {
+        let log = |ops: &[oplog::Op]| oplog::ops_to_csv(ops).unwrap();
+        let mut millis = 0;
+        let shared = vec![task(1, "shared")];
+        let base = changes(&mut millis, "a", &[], &shared);
+
+        // both devices add a task offline, which happen to get the same id
+        let ops_a = changes(
+            &mut millis,
+            "a",
+            &shared,
+            &[task(5, "on a"), task(1, "shared")],
+        );
+        let ops_b = changes(
+            &mut millis,
+            "b",
+            &shared,
+            &[task(5, "on b"), task(1, "by b")],
+        );
+        let own_a = [base.clone(), ops_a].concat();
+        let logs = vec![
+            (
+                "ops/a.csv".to_string(),
+                [log(&base), log(&own_a), log(&base)],
+            ),
+            (
+                "ops/b.csv".to_string(),
+                [Vec::new(), Vec::new(), log(&ops_b)],
+            ),
+        ];
+        let (tasks, merged) = merge_logs(logs, "a").unwrap();
+        let mut descs: Vec<&str> = tasks.iter().map(|task| task.desc.as_str()).collect();
+        descs.sort();
+        assert_eq!(descs, ["by b", "on a", "on b"]);
+        let on_a = tasks.iter().find(|task| task.desc == "on a").unwrap();
+        assert!(on_a.id != 5 && on_a.id != 1);
+
+        // the other device then gets the same tasks from the merged logs
+        let logs = vec![
+            (
+                "ops/a.csv".to_string(),
+                [log(&base), log(&base), merged[0].1.clone()],
+            ),
+            (
+                "ops/b.csv".to_string(),
+                [Vec::new(), log(&ops_b), merged[1].1.clone()],
+            ),
+        ];
+        let (again, _) = merge_logs(logs, "b").unwrap();
+        assert!(again == tasks);
+    }
+}
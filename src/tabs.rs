@@ -0,0 +1,81 @@
+use chrono::Local;
+
+use crate::{
+    config::{config, View},
+    state::Task,
+};
+
+/// A tab of the task list, showing some of the tasks
+#[derive(Clone)]
+pub enum Tab {
+    All,
+    Pending,
+    Done,
+    /// pending tasks due today
+    Today,
+    /// pending tasks whose due date has passed
+    Overdue,
+    /// a view from the configuration
+    Saved(View),
+}
+
+impl Tab {
+    /// The built-in tabs followed by the views of the configuration
+    pub fn all() -> Vec<Tab> {
+        let mut tabs = vec![Tab::All, Tab::Pending, Tab::Done, Tab::Today, Tab::Overdue];
+        tabs.extend(config().views.iter().cloned().map(Tab::Saved));
+        tabs
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Tab::All => "All",
+            Tab::Pending => "Pending",
+            Tab::Done => "Done",
+            Tab::Today => "Today",
+            Tab::Overdue => "Overdue",
+            Tab::Saved(view) => &view.name,
+        }
+    }
+
+    pub fn matches(&self, task: &Task) -> bool {
+        let now = Local::now();
+        match self {
+            Tab::All => true,
+            Tab::Pending => !task.completed,
+            Tab::Done => task.completed,
+            Tab::Today => {
+                !task.completed
+                    && task
+                        .due
+                        .is_some_and(|due| due.date_naive() == now.date_naive())
+            }
+            Tab::Overdue => !task.completed && task.due.is_some_and(|due| due < now),
+            Tab::Saved(view) => view.matches(task),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn check_tabs() {
+        let mut task = Task {
+            due: Some(Local::now() - Duration::minutes(1)),
+            ..Default::default()
+        };
+        assert!(Tab::Overdue.matches(&task));
+        assert!(Tab::Pending.matches(&task));
+        assert!(!Tab::Done.matches(&task));
+        task.due = Some(Local::now() + Duration::days(2));
+        assert!(!Tab::Today.matches(&task));
+        assert!(!Tab::Overdue.matches(&task));
+        task.completed = true;
+        assert!(Tab::Done.matches(&task));
+        assert!(Tab::All.matches(&task));
+    }
+}
@@ -0,0 +1,331 @@
+use std::{fs, path::PathBuf, str::FromStr};
+
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{config_path, expand_home},
+    Result,
+};
+
+/// Theme used when none is chosen
+pub const DEFAULT_THEME: &str = "dark";
+/// Themes which are always available
+pub const BUILTIN_THEMES: [&str; 4] = ["dark", "light", "high-contrast", "solarized"];
+/// Folder next to the configuration file holding the themes of the user
+pub(crate) const THEMES_FOLDER: &str = "themes";
+/// How many themes can be layered on top of each other
+const MAX_DEPTH: usize = 8;
+
+/// Colors of every part of the TUI
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Theme {
+    /// borders of the status bar, the keymap and the editing popup
+    pub border: Color,
+    /// border of the task list
+    pub tasks: Color,
+    /// border of the trash
+    pub trash: Color,
+    /// border of the popup asking before quitting
+    pub warning: Color,
+    /// text typed in the editing popup and of the quit popup
+    pub text: Color,
+    /// hint shown while nothing is typed yet
+    pub placeholder: Color,
+    /// background of the selected task
+    pub selected: Color,
+    /// text of the selected task
+    pub selected_text: Color,
+    /// dates next to the tasks
+    pub date: Color,
+    /// marker of completed tasks
+    pub completed: Color,
+    /// marker of pending tasks
+    pub pending: Color,
+    /// characters of a task matching the search
+    pub matched: Color,
+    /// background of the tasks marked for actions applying to several tasks
+    pub marked: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    fn dark() -> Self {
+        Self {
+            border: Color::Blue,
+            tasks: Color::Green,
+            trash: Color::Red,
+            warning: Color::Red,
+            text: Color::White,
+            placeholder: Color::DarkGray,
+            selected: Color::LightBlue,
+            selected_text: Color::Black,
+            date: Color::Gray,
+            completed: Color::Green,
+            pending: Color::Reset,
+            matched: Color::Yellow,
+            marked: Color::DarkGray,
+        }
+    }
+
+    fn light() -> Self {
+        Self {
+            border: Color::Blue,
+            tasks: Color::Green,
+            trash: Color::Red,
+            warning: Color::Red,
+            text: Color::Black,
+            placeholder: Color::Gray,
+            selected: Color::Blue,
+            selected_text: Color::White,
+            date: Color::DarkGray,
+            completed: Color::Green,
+            pending: Color::Reset,
+            matched: Color::Magenta,
+            marked: Color::Gray,
+        }
+    }
+
+    fn high_contrast() -> Self {
+        Self {
+            border: Color::White,
+            tasks: Color::White,
+            trash: Color::LightYellow,
+            warning: Color::LightRed,
+            text: Color::White,
+            placeholder: Color::Gray,
+            selected: Color::Yellow,
+            selected_text: Color::Black,
+            date: Color::White,
+            completed: Color::LightGreen,
+            pending: Color::LightRed,
+            matched: Color::LightCyan,
+            marked: Color::Blue,
+        }
+    }
+
+    fn solarized() -> Self {
+        Self {
+            border: Color::Rgb(0x26, 0x8b, 0xd2),
+            tasks: Color::Rgb(0x85, 0x99, 0x00),
+            trash: Color::Rgb(0xdc, 0x32, 0x2f),
+            warning: Color::Rgb(0xcb, 0x4b, 0x16),
+            text: Color::Rgb(0x93, 0xa1, 0xa1),
+            placeholder: Color::Rgb(0x58, 0x6e, 0x75),
+            selected: Color::Rgb(0x26, 0x8b, 0xd2),
+            selected_text: Color::Rgb(0xfd, 0xf6, 0xe3),
+            date: Color::Rgb(0x83, 0x94, 0x96),
+            completed: Color::Rgb(0x85, 0x99, 0x00),
+            pending: Color::Rgb(0xb5, 0x89, 0x00),
+            matched: Color::Rgb(0xd3, 0x36, 0x82),
+            marked: Color::Rgb(0x07, 0x36, 0x42),
+        }
+    }
+
+    /// One of the built-in themes
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::dark()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "solarized" => Some(Self::solarized()),
+            _ => None,
+        }
+    }
+
+    fn color_mut(&mut self, key: &str) -> Option<&mut Color> {
+        Some(match key {
+            "border" => &mut self.border,
+            "tasks" => &mut self.tasks,
+            "trash" => &mut self.trash,
+            "warning" => &mut self.warning,
+            "text" => &mut self.text,
+            "placeholder" => &mut self.placeholder,
+            "selected" => &mut self.selected,
+            "selected_text" => &mut self.selected_text,
+            "date" => &mut self.date,
+            "completed" => &mut self.completed,
+            "pending" => &mut self.pending,
+            "matched" => &mut self.matched,
+            "marked" => &mut self.marked,
+            _ => return None,
+        })
+    }
+
+    /// Load a theme by name
+    ///
+    /// The name is a built-in theme, a file in the themes folder next to the
+    /// configuration without its extension, or the path of a theme file
+    pub fn load(name: &str) -> Result<Self> {
+        Self::load_nested(name, 0)
+    }
+
+    fn load_nested(name: &str, depth: usize) -> Result<Self> {
+        if let Some(theme) = Self::builtin(name) {
+            return Ok(theme);
+        }
+        if depth >= MAX_DEPTH {
+            return Err(format!("theme `{name}` builds on too many other themes").into());
+        }
+        let path = theme_file(name).ok_or_else(|| format!("unknown theme `{name}`"))?;
+        let contents = fs::read_to_string(&path)?;
+        let spec: ThemeSpec = if is_yaml(&path) {
+            serde_yaml::from_str(&contents).map_err(|err| format!("{}: {err}", path.display()))?
+        } else {
+            toml::from_str(&contents).map_err(|err| format!("{}: {err}", path.display()))?
+        };
+        spec.resolve_nested(depth + 1)
+            .map_err(|err| format!("{}: {err}", path.display()).into())
+    }
+}
+
+/// Colors of a theme as written in the configuration or a theme file
+///
+/// `name` is the theme the colors are applied on top of, colors which are not
+/// set are taken from it. Colors are names like `blue` and `lightblue`,
+/// indexes like `8` or `#rrggbb`
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeSpec {
+    pub name: Option<String>,
+    pub border: Option<String>,
+    pub tasks: Option<String>,
+    pub trash: Option<String>,
+    pub warning: Option<String>,
+    pub text: Option<String>,
+    pub placeholder: Option<String>,
+    pub selected: Option<String>,
+    pub selected_text: Option<String>,
+    pub date: Option<String>,
+    pub completed: Option<String>,
+    pub pending: Option<String>,
+    pub matched: Option<String>,
+    pub marked: Option<String>,
+}
+
+impl ThemeSpec {
+    pub fn colors(&self) -> [(&'static str, &Option<String>); 13] {
+        [
+            ("border", &self.border),
+            ("tasks", &self.tasks),
+            ("trash", &self.trash),
+            ("warning", &self.warning),
+            ("text", &self.text),
+            ("placeholder", &self.placeholder),
+            ("selected", &self.selected),
+            ("selected_text", &self.selected_text),
+            ("date", &self.date),
+            ("completed", &self.completed),
+            ("pending", &self.pending),
+            ("matched", &self.matched),
+            ("marked", &self.marked),
+        ]
+    }
+
+    /// The theme this describes
+    pub fn resolve(&self) -> Result<Theme> {
+        self.resolve_nested(0)
+    }
+
+    fn resolve_nested(&self, depth: usize) -> Result<Theme> {
+        let mut theme = Theme::load_nested(self.name.as_deref().unwrap_or(DEFAULT_THEME), depth)?;
+        self.apply(&mut theme)?;
+        Ok(theme)
+    }
+
+    /// Set the colors given here on a theme
+    pub fn apply(&self, theme: &mut Theme) -> Result<()> {
+        for (key, color) in self.colors() {
+            if let Some(color) = color {
+                // every key of `colors` is a color of the theme
+                if let Some(slot) = theme.color_mut(key) {
+                    *slot = parse_color(color)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a color of a theme
+pub fn parse_color(color: &str) -> std::result::Result<Color, String> {
+    Color::from_str(color).map_err(|_| format!("unknown color `{color}`"))
+}
+
+fn is_yaml(path: &std::path::Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml")
+}
+
+/// Folder holding the themes of the user
+pub fn themes_dir() -> PathBuf {
+    config_path()
+        .parent()
+        .map(|dir| dir.join(THEMES_FOLDER))
+        .unwrap_or_else(|| PathBuf::from(THEMES_FOLDER))
+}
+
+/// File of a theme which is not built in
+fn theme_file(name: &str) -> Option<PathBuf> {
+    let path = expand_home(name);
+    if path.is_file() {
+        return Some(path);
+    }
+    let dir = themes_dir();
+    ["toml", "yaml", "yml"]
+        .iter()
+        .map(|ext| dir.join(format!("{name}.{ext}")))
+        .find(|path| path.is_file())
+}
+
+/// Names of every theme which can be chosen, the built-in ones come first
+pub fn available() -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_THEMES.iter().map(|name| name.to_string()).collect();
+    let mut user: Vec<String> = fs::read_dir(themes_dir())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_yaml(path) || path.extension().is_some_and(|ext| ext == "toml"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .filter(|name| !names.contains(name))
+        .collect();
+    user.sort();
+    user.dedup();
+    names.extend(user);
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_theme_files() {
+        let dir = std::env::temp_dir().join(format!("todo-cli-theme-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("base.yaml");
+        fs::write(&base, "name: solarized\ndate: \"#808080\"\n").unwrap();
+        let top = dir.join("top.toml");
+        fs::write(
+            &top,
+            format!("name = \"{}\"\nborder = \"magenta\"\n", base.display()),
+        )
+        .unwrap();
+
+        let theme = Theme::load(top.to_str().unwrap()).unwrap();
+        assert_eq!(theme.border, Color::Magenta);
+        assert_eq!(theme.date, Color::Rgb(0x80, 0x80, 0x80));
+        assert_eq!(theme.tasks, Theme::solarized().tasks);
+
+        fs::write(&base, format!("name: {}\n", top.display())).unwrap();
+        assert!(Theme::load(top.to_str().unwrap()).is_err());
+        fs::write(&base, "border: blurple\n").unwrap();
+        assert!(Theme::load(base.to_str().unwrap()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
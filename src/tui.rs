@@ -1,66 +1,193 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
 use crossterm::{
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
+use signal_hook::consts::{SIGINT, SIGTERM};
 
 use crate::{
     app::App,
+    config::config,
+    crypto,
     events::handle_events,
-    files::{check_existing_metadata, enter_data_to_file, read_data_from_file},
+    files::{
+        archive_expired, check_existing_metadata, enter_data_to_file, read_data_from_file,
+        read_recovery, remove_recovery, write_recovery,
+    },
     state::State,
+    theme::DEFAULT_THEME,
     ui::ui,
+    watch::Watcher,
     Result,
 };
 
+type Term = Terminal<CrosstermBackend<std::io::Stderr>>;
+
+/// Shown once the terminal is back when the changes of a session could not be saved
+const KEPT_CHANGES: &str =
+    "The unsaved changes were kept and will be restored the next time the TUI starts";
+
 /// Run all the startup routines for creating an alternate terminal window
 fn startup() -> Result<()> {
-    execute!(std::io::stderr(), EnterAlternateScreen)?;
+    execute!(
+        std::io::stderr(),
+        EnterAlternateScreen,
+        EnableBracketedPaste
+    )?;
+    if config().general.mouse {
+        execute!(std::io::stderr(), EnableMouseCapture)?;
+    }
     enable_raw_mode()?;
     Ok(())
 }
 
 /// Shutdown routines which must be called to return the terminal back to its original state
 fn shutdown() -> Result<()> {
-    execute!(std::io::stderr(), LeaveAlternateScreen)?;
+    if config().general.mouse {
+        execute!(std::io::stderr(), DisableMouseCapture)?;
+    }
+    execute!(
+        std::io::stderr(),
+        DisableBracketedPaste,
+        LeaveAlternateScreen
+    )?;
     disable_raw_mode()?;
     Ok(())
 }
 
+/// Give the terminal back before a panic is reported, so that the message can be read
+fn install_panic_hook() {
+    let report = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = shutdown();
+        report(info);
+    }));
+}
+
+/// Flag raised when the process is asked to stop, instead of being killed right away
+fn register_signals() -> Result<Arc<AtomicBool>> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut signals = vec![SIGINT, SIGTERM];
+    // sent when the terminal is closed
+    #[cfg(unix)]
+    signals.push(signal_hook::consts::SIGHUP);
+    for signal in signals {
+        signal_hook::flag::register(signal, Arc::clone(&stop))?;
+    }
+    Ok(stop)
+}
+
+/// Draw the app and handle the events until the user leaves
+///
+/// The data file is checked in between, as events are waited for at most 250ms.
+/// Returns whether the tasks must be saved, or `None` when the process was asked to stop
+fn event_loop(
+    terminal: &mut Term,
+    app: &mut App,
+    state: &mut State,
+    watcher: &mut Watcher,
+    stop: &AtomicBool,
+) -> Result<Option<bool>> {
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        ui(terminal, app, state)?;
+        if let Some(save) = handle_events(app, state)? {
+            return Ok(Some(save));
+        }
+        watcher.check(app, state);
+    }
+}
+
+/// The stored tasks, without the completed ones due to be archived
+///
+/// A store which cannot be read is an error rather than an empty list, which
+/// would replace the tasks once saved
+fn load_state() -> Result<State> {
+    if !check_existing_metadata() {
+        return Ok(State::new());
+    }
+    let mut state = read_data_from_file()?;
+    // the tasks are written without the ones archived, so the watcher starts from them
+    if archive_expired(&mut state)? > 0 {
+        enter_data_to_file(&mut state)?;
+    }
+    Ok(state)
+}
+
 /// Run the main loop for tui application
-fn implement_tui() -> Result<()> {
+///
+/// Returns whether the changes were kept for the next launch instead of being saved
+fn implement_tui(mut app: App, mut state: State) -> Result<bool> {
     let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stderr()))?;
-    let mut state;
-    if check_existing_metadata() {
-        state = match read_data_from_file() {
-            Ok(s) => s,
-            Err(_) => {
-                println!("There was an error in reading metadata");
-                State::new()
-            }
+    let mut watcher = Watcher::new(&state);
+    // the changes of a session which ended abruptly are a single step which can be undone
+    if let Some(tasks) = read_recovery()? {
+        let step = state.changes_to(&tasks);
+        if !step.is_empty() {
+            state.apply_changes(step);
+            app.notice = Some("Restored the unsaved changes of the last session".to_string());
         }
-    } else {
-        state = State::new();
     }
-    let mut app = App::new();
-    loop {
-        ui(&mut terminal, &mut app, &state)?;
-        if let Some(option) = handle_events(&mut app, &mut state)? {
+    let stop = register_signals()?;
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        event_loop(&mut terminal, &mut app, &mut state, &mut watcher, &stop)
+    }));
+    match outcome {
+        Ok(Ok(Some(save))) => {
             // upload the new tasks
-            if option {
-                enter_data_to_file(&state)?;
+            // recovered changes are only dropped once they are saved
+            if save {
+                watcher.save(&state)?;
+                remove_recovery()?;
+            }
+            Ok(false)
+        }
+        // asked to stop, or the terminal could not be drawn or read
+        Ok(result) => {
+            let kept = write_recovery(&state)?;
+            result.map(|_| kept)
+        }
+        Err(panic) => {
+            // the terminal was given back by the panic hook
+            if write_recovery(&state).unwrap_or(false) {
+                eprintln!("{KEPT_CHANGES}");
             }
-            break;
+            panic::resume_unwind(panic)
         }
     }
-    Ok(())
 }
 
 /// The wrapper function which runs the complete application
-pub fn run() -> Result<()> {
+///
+/// `theme` replaces the theme chosen in the configuration
+pub fn run(theme: Option<&str>) -> Result<()> {
+    // the passphrase is asked for and the theme is checked before the terminal is taken over
+    crypto::unlock()?;
+    let mut app = App::new();
+    app.set_theme(
+        theme
+            .or(config().theme.name.as_deref())
+            .unwrap_or(DEFAULT_THEME),
+    )?;
+    // read before the terminal is taken over, so that an error is shown as usual
+    let state = load_state()?;
+    install_panic_hook();
     startup()?;
-    let result = implement_tui();
+    let result = implement_tui(app, state);
     shutdown()?;
-    result?;
+    if result? {
+        eprintln!("{KEPT_CHANGES}");
+    }
     Ok(())
 }
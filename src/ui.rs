@@ -1,40 +1,100 @@
 use crate::{
     app::{App, Status},
+    form::{Field, TaskForm},
     format_date,
-    state::{ListItem, State},
+    input::Input,
+    keymap::Action,
+    search,
+    state::{ListItem, State, Task},
+    theme::Theme,
+    watch::Conflict,
     Result,
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Style, Stylize},
-    widgets::{Block, BorderType, Borders, Clear, Padding, Paragraph, Widget},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{
+        block::{Position, Title},
+        Block, BorderType, Borders, Clear, Padding, Paragraph, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, Tabs, Widget, Wrap,
+    },
     Frame, Terminal,
 };
-
-use tui_widget_list::{List, ListState};
+use unicode_width::UnicodeWidthStr;
 
 type Term = Terminal<CrosstermBackend<std::io::Stderr>>;
 
+/// A task in the list along with the theme it is drawn with
+struct ThemedItem<'a> {
+    item: &'a ListItem,
+    theme: &'a Theme,
+    /// indices of the chars of the description matching the search
+    matched: &'a [usize],
+    /// whether the task is marked for the actions applying to several tasks
+    marked: bool,
+}
+
+impl Widget for ThemedItem<'_> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer) {
+        render_list_item(self.item, self.theme, self.matched, self.marked, area, buf);
+    }
+}
+
+/// A task shown in the list, its index in `State::ids` with the chars matching the search
+type Row = (usize, Vec<usize>);
+
+/// Tasks of the current tab to show in the list
+///
+/// While searching only the matches are shown, best first. Otherwise every
+/// task is shown with the last search highlighted
+fn list_rows(app: &App, state: &State) -> Vec<Row> {
+    let visible = app.visible(state);
+    if let Status::Searching { query } = &app.status {
+        return search::find(state, &visible, query.text())
+            .into_iter()
+            .map(|found| (found.idx, found.chars))
+            .collect();
+    }
+    visible
+        .into_iter()
+        .map(|idx| {
+            let matched = app
+                .search
+                .as_deref()
+                .zip(state.tasks.get(&state.ids[idx]))
+                .map(|(query, item)| search::matched_chars(query, &item.task.desc))
+                .unwrap_or_default();
+            (idx, matched)
+        })
+        .collect()
+}
+
 /// return the central rect for the popup
 ///
 /// Consider first rendering the clean widget to improve popup readability
 fn get_popup_rect(area: Rect) -> Rect {
+    centered_rect(area, 70, 50)
+}
+
+/// A rect in the middle of the area taking the given percentages of its size
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
     let layout = Layout::new(
         Direction::Vertical,
         [
-            Constraint::Percentage(25),
-            Constraint::Percentage(50),
-            Constraint::Percentage(25),
+            Constraint::Percentage((100 - height) / 2),
+            Constraint::Percentage(height),
+            Constraint::Percentage((100 - height) / 2),
         ],
     )
     .split(area);
     let central_layout = Layout::new(
         Direction::Horizontal,
         [
-            Constraint::Percentage(15),
-            Constraint::Percentage(70),
-            Constraint::Percentage(15),
+            Constraint::Percentage((100 - width) / 2),
+            Constraint::Percentage(width),
+            Constraint::Percentage((100 - width) / 2),
         ],
     )
     .split(layout[1]);
@@ -48,70 +108,228 @@ fn get_layout() -> Layout {
         [
             // the status bar is at top
             Constraint::Length(3),
+            // tabs of the task list
+            Constraint::Length(1),
             // tasks list with atleast 1 row
             Constraint::Min(1),
-            // keymaps available
-            Constraint::Length(2),
+            // keymaps available, on up to two lines
+            Constraint::Length(3),
         ],
     )
 }
 
+/// Width of the column of the ●/◯ glyph at the start of every task, clicking it toggles the task
+pub const GLYPH_COLUMN: u16 = 3;
+
+/// Terminals narrower than this show the details below the list instead of next to it
+const WIDE_LAYOUT: u16 = 100;
+
+/// Layout of the task list along with the details of the selected task
+fn get_list_layout(area: Rect) -> Layout {
+    if area.width >= WIDE_LAYOUT {
+        Layout::new(
+            Direction::Horizontal,
+            [Constraint::Percentage(60), Constraint::Percentage(40)],
+        )
+    } else {
+        Layout::new(
+            Direction::Vertical,
+            [Constraint::Percentage(50), Constraint::Percentage(50)],
+        )
+    }
+}
+
 /// Determine and render the content for status section of app
-fn render_status_widget(mode: &Status, f: &mut Frame, size: Rect) {
+///
+/// The number of marked tasks is shown next to the mode
+fn render_status_widget(app: &App, state: &State, f: &mut Frame, size: Rect) {
+    let mode = match &app.status {
+        Status::Idle if app.visual.is_some() => "Visual Mode",
+        Status::Idle => "Idle Mode",
+        Status::Searching { .. } => "Search Mode",
+        Status::Prompt { .. } => "Batch Edit",
+        Status::Editing {
+            previous: _,
+            edit: _,
+        } => "Editing Mode",
+        Status::Trash { .. } => "Trash",
+        Status::Exiting => "Exiting",
+        Status::Conflict { .. } => "Conflict",
+    };
     f.render_widget(
         Paragraph::new({
-            match mode {
-                Status::Idle => "Idle Mode",
-                Status::Editing {
-                    previous: _,
-                    edit: _,
-                } => "Editing Mode",
-                Status::Exiting => "Exiting",
+            match state.marked.len() {
+                0 => mode.to_string(),
+                marked => format!("{mode} \u{ff5c} {marked} selected"),
             }
         })
         .alignment(Alignment::Center)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::new().fg(Color::Blue))
-                .border_type(BorderType::Rounded),
+                .border_style(Style::new().fg(app.theme.border))
+                .border_type(BorderType::Rounded)
+                .title(format!("theme: {}", app.theme_name))
+                .title_alignment(Alignment::Right),
         ),
         size,
     );
 }
 
+/// Tabs of the task list with the number of tasks in each, the first ones along with their number key
+///
+/// Where each title is drawn is kept for clicking on it
+fn render_tabs_widget(f: &mut Frame, app: &mut App, state: &State, size: Rect) {
+    let titles: Vec<String> = app
+        .tabs
+        .iter()
+        .enumerate()
+        .map(|(idx, tab)| {
+            let count = state
+                .tasks
+                .values()
+                .filter(|item| tab.matches(&item.task))
+                .count();
+            if idx < 9 {
+                format!("{} {} ({count})", idx + 1, tab.name())
+            } else {
+                format!("{} ({count})", tab.name())
+            }
+        })
+        .collect();
+    // titles are padded with a space on both sides and divided by a line
+    let mut x = size.x;
+    app.tab_areas = titles
+        .iter()
+        .map(|title| {
+            let width = title.width() as u16 + 2;
+            let area = Rect { x, width, ..size }.intersection(size);
+            x = x.saturating_add(width + 1);
+            area
+        })
+        .collect();
+    f.render_widget(
+        Tabs::new(titles)
+            .select(app.tab)
+            .style(Style::new().fg(app.theme.placeholder))
+            .highlight_style(
+                Style::new()
+                    .bg(app.theme.selected)
+                    .fg(app.theme.selected_text),
+            ),
+        size,
+    );
+}
+
+/// Lines of the actions of the footer fitting in `area`, along with where each action is drawn
+fn footer_buttons(
+    items: Vec<(String, Action)>,
+    area: Rect,
+) -> (Vec<Line<'static>>, Vec<(Rect, Action)>) {
+    const SEPARATOR: &str = " \u{ff5c} ";
+    let separator = SEPARATOR.width() as u16;
+    let mut lines = vec![Line::default()];
+    let mut buttons = Vec::new();
+    let mut x = 0;
+    for (item, action) in items {
+        let width = item.width() as u16;
+        if x > 0 && x + separator + width > area.width {
+            lines.push(Line::default());
+            x = 0;
+        }
+        let row = lines.len() as u16 - 1;
+        let line = lines.last_mut().unwrap();
+        if x > 0 {
+            line.spans.push(Span::raw(SEPARATOR));
+            x += separator;
+        }
+        if row < area.height {
+            let button = Rect::new(area.x + x, area.y + row, width, 1).intersection(area);
+            buttons.push((button, action));
+        }
+        line.spans.push(Span::raw(item));
+        x += width;
+    }
+    (lines, buttons)
+}
+
 /// Determine and render the content for keymap section of app
-fn render_keymap_widget(mode: &Status, f: &mut Frame, size: Rect) {
+///
+/// While searching it holds the query instead, along with the number of matches.
+/// The actions shown when idle are kept for clicking on them
+fn render_keymap_widget(app: &mut App, f: &mut Frame, matches: usize, size: Rect) {
     let k_layout = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
         .split(size);
+    let text = Rect {
+        y: k_layout[0].y + 1,
+        height: k_layout[0].height.saturating_sub(1),
+        ..k_layout[0]
+    };
+    // generated from the keymap so that it shows the keys in use
+    let (footer, buttons) = footer_buttons(app.keymap.footer_items(), text);
+    app.buttons = match app.status {
+        Status::Idle => buttons,
+        _ => Vec::new(),
+    };
+    let theme = &app.theme;
     f.render_widget(
         Paragraph::new({
-            match mode {
-                Status::Idle => "e:Edit \u{ff5c} x:Delete \u{ff5c} i:New \u{ff5c} q:Quit  \u{ff5c} Enter:Toggle status\u{ff5c} \u{2191}/\u{2193}:Select",
-                Status::Editing{edit: _, previous: _} => "enter - submit task, esc - cancel",
-                Status::Exiting => "",
+            match &app.status {
+                Status::Idle => Text::from(footer),
+                Status::Searching { query } => format!("/{}", query.text()).into(),
+                Status::Prompt { field, input, .. } => format!("{}: {}", field.label(), input.text()).into(),
+                Status::Trash { .. } => app.keymap.trash_footer().into(),
+                Status::Editing{edit: _, previous: _} => "Enter:Save \u{ff5c} Alt-Enter:New line \u{ff5c} Tab:Next field \u{ff5c} Esc:Cancel \u{ff5c} ^w/^u:Delete word/line".into(),
+                Status::Exiting => Text::default(),
+                Status::Conflict { .. } => "m:Keep mine \u{ff5c} t:Take theirs \u{ff5c} M/T:Same for all".into(),
             }
         })
+        .wrap(Wrap { trim: true })
         .block(
             Block::default()
                 .borders(Borders::TOP)
-                .border_style(Style::new().fg(Color::Blue))
+                .border_style(Style::new().fg(theme.border))
                 .border_type(BorderType::Rounded),
         ),k_layout[0],
     );
+    // the cursor goes after the text before the input
+    let typed = match &app.status {
+        Status::Searching { query } => Some((1, query)),
+        Status::Prompt { field, input, .. } => Some((field.label().width() + 2, input)),
+        _ => None,
+    };
+    if let Some((before, input)) = typed {
+        let column =
+            ((before + input.cursor_column()) as u16).min(k_layout[0].width.saturating_sub(1));
+        f.set_cursor(k_layout[0].x + column, k_layout[0].y + 1);
+    }
+    let error = match &app.status {
+        Status::Prompt { error, .. } => error.as_ref(),
+        _ => None,
+    };
     f.render_widget(
         Paragraph::new({
-            match mode {
-                Status::Idle => "\u{25cf}: Completed \u{ff5c}  \u{25ef}: Incomplete",
-                _ => "",
+            match &app.status {
+                _ if error.is_some() => error.cloned().unwrap_or_default(),
+                Status::Idle if app.notice.is_some() => app.notice.clone().unwrap_or_default(),
+                Status::Idle => "\u{25cf}: Completed \u{ff5c}  \u{25ef}: Incomplete".to_string(),
+                Status::Searching { .. } => format!("{matches} matches \u{ff5c} Esc:Clear"),
+                Status::Prompt { .. } => "Enter:Apply \u{ff5c} Esc:Cancel".to_string(),
+                _ => String::new(),
             }
         })
+        .wrap(Wrap { trim: true })
+        .fg(if error.is_some() {
+            theme.warning
+        } else {
+            Color::Reset
+        })
         .block(
             Block::default()
                 .borders(Borders::TOP)
-                .border_style(Style::new().fg(Color::Blue))
+                .border_style(Style::new().fg(theme.border))
                 .border_type(BorderType::Rounded),
         )
         .alignment(Alignment::Right),
@@ -120,36 +338,125 @@ fn render_keymap_widget(mode: &Status, f: &mut Frame, size: Rect) {
 }
 
 /// UI for the popup to be shown when editing or adding a new task
-fn render_editing_widget(f: &mut Frame, data: &str, area: Rect) {
-    f.render_widget(
-        Paragraph::new({
-            if data.is_empty() {
-                "Enter your task details"
-            } else {
-                data
-            }
-        })
-        .fg({
-            if data.is_empty() {
-                Color::DarkGray
-            } else {
-                Color::White
-            }
-        })
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_type(BorderType::Double)
-                .border_style(Style::default().fg(Color::Blue))
-                .padding(Padding::horizontal(1))
-                .title("Editing Task"),
-        ),
-        get_popup_rect(area),
-    );
+///
+/// It holds every field of the task along with the size of the focused one
+fn render_editing_widget(f: &mut Frame, theme: &Theme, form: &mut TaskForm, area: Rect) {
+    let (chars, lines) = form.input(form.focus).counts();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Double)
+        .border_style(Style::default().fg(theme.border))
+        .padding(Padding::horizontal(1))
+        .title("Editing Task")
+        .title(
+            Title::from(format!("{chars} chars, {lines} lines"))
+                .alignment(Alignment::Right)
+                .position(Position::Bottom),
+        );
+    let area = centered_rect(area, 80, 80);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let layout = Layout::new(
+        Direction::Vertical,
+        [
+            Constraint::Min(3),
+            Constraint::Min(3),
+            Constraint::Length(3),
+            // why the task could not be saved
+            Constraint::Length(1),
+        ],
+    )
+    .split(inner);
+    let row = Layout::new(
+        Direction::Horizontal,
+        [
+            Constraint::Length(12),
+            Constraint::Length(22),
+            Constraint::Min(10),
+        ],
+    )
+    .split(layout[2]);
+    let focus = form.focus;
+    for (field, area) in Field::ALL
+        .into_iter()
+        .zip([layout[0], layout[1], row[0], row[1], row[2]])
+    {
+        render_field(f, theme, field, form.input_mut(field), field == focus, area);
+    }
+    if let Some(error) = &form.error {
+        f.render_widget(Paragraph::new(error.as_str()).fg(theme.warning), layout[3]);
+    }
+}
+
+/// A field of the editing popup
+///
+/// Multiline fields wrap and scroll down to follow the cursor, the others scroll sideways
+fn render_field(
+    f: &mut Frame,
+    theme: &Theme,
+    field: Field,
+    input: &mut Input,
+    focused: bool,
+    area: Rect,
+) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(Style::default().fg(if focused {
+            theme.border
+        } else {
+            theme.placeholder
+        }))
+        .title(field.label());
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    if inner.width == 0 || inner.height == 0 {
+        return;
+    }
+    if input.is_empty() {
+        f.render_widget(
+            Paragraph::new(field.placeholder()).fg(theme.placeholder),
+            inner,
+        );
+        if focused {
+            f.set_cursor(inner.x, inner.y);
+        }
+        return;
+    }
+
+    let (row, column) = if input.is_multiline() {
+        let height = inner.height as usize;
+        input.width = inner.width as usize;
+        let rows = input.rows();
+        let row = input.cursor_row();
+        input.scroll = scroll_offset(input.scroll, Some(row), height, rows.len());
+        let lines: Vec<Line> = rows
+            .iter()
+            .skip(input.scroll)
+            .take(height)
+            .map(|(start, end)| Line::from(&input.text()[*start..*end]))
+            .collect();
+        f.render_widget(Paragraph::new(lines).fg(theme.text), inner);
+        ((row - input.scroll) as u16, input.cursor_column() as u16)
+    } else {
+        let column = input.cursor_column() as u16;
+        let scroll = (column + 1).saturating_sub(inner.width);
+        f.render_widget(
+            Paragraph::new(input.text())
+                .fg(theme.text)
+                .scroll((0, scroll)),
+            inner,
+        );
+        (0, column - scroll)
+    };
+    if focused {
+        f.set_cursor(inner.x + column.min(inner.width - 1), inner.y + row);
+    }
 }
 
 /// popup to confirm exit
-fn render_exiting_widget(f: &mut Frame, area: Rect) {
+fn render_exiting_widget(f: &mut Frame, theme: &Theme, area: Rect) {
     let center_popup = Layout::new(
         Direction::Vertical,
         [
@@ -161,13 +468,13 @@ fn render_exiting_widget(f: &mut Frame, area: Rect) {
     .split(get_popup_rect(area));
     f.render_widget(
         Paragraph::new("y to quit, n to cancel, x to quit without saving")
-            .fg(Color::White)
+            .fg(theme.text)
             .alignment(Alignment::Center)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .border_type(BorderType::Double)
-                    .border_style(Style::default().fg(Color::Red))
+                    .border_style(Style::default().fg(theme.warning))
                     .title_alignment(Alignment::Center)
                     .title("Are you sure you want to quit?"),
             ),
@@ -175,57 +482,341 @@ fn render_exiting_widget(f: &mut Frame, area: Rect) {
     );
 }
 
+/// popup showing both versions of a task changed here and in the data file
+fn render_conflict_widget(f: &mut Frame, theme: &Theme, conflicts: &[Conflict], area: Rect) {
+    let Some(conflict) = conflicts.first() else {
+        return;
+    };
+    let version = |name: &str, task: &Option<Task>| {
+        let task = match task {
+            Some(task) if task.completed => format!("\u{25ef} {}", task.desc),
+            Some(task) => format!("\u{25cf} {}", task.desc),
+            None => "deleted".to_string(),
+        };
+        Line::from(vec![
+            Span::styled(format!("{name:<7}"), Style::new().fg(theme.date)),
+            Span::raw(task),
+        ])
+    };
+    let id = conflict
+        .ours
+        .as_ref()
+        .or(conflict.theirs.as_ref())
+        .map(|task| task.id)
+        .unwrap_or_default();
+    let mut lines = vec![
+        Line::from(format!("Task {id} was changed here and in the data file")),
+        Line::default(),
+        version("Mine", &conflict.ours),
+        version("Theirs", &conflict.theirs),
+    ];
+    if conflicts.len() > 1 {
+        lines.push(Line::default());
+        lines.push(Line::styled(
+            format!("{} more to settle", conflicts.len() - 1),
+            Style::new().fg(theme.placeholder),
+        ));
+    }
+    // as high as its lines, so that both versions are seen in short terminals
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup = Rect {
+        y: area.y + (area.height - height) / 2,
+        height,
+        ..get_popup_rect(area)
+    };
+    f.render_widget(
+        Paragraph::new(lines)
+            .fg(theme.text)
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Double)
+                    .border_style(Style::default().fg(theme.warning))
+                    .title_alignment(Alignment::Center)
+                    .title("Changed in both places")
+                    .padding(Padding::horizontal(1)),
+            ),
+        popup,
+    );
+}
+
+/// List of deleted tasks along with the time of deletion
+fn render_trash_widget(f: &mut Frame, theme: &Theme, state: &State, selected: usize, size: Rect) {
+    // rows inside the borders
+    let height = size.height.saturating_sub(2) as usize;
+    let start_index = scroll_offset(0, Some(selected), height, state.trash.len());
+    let lines: Vec<Line> = state
+        .trash
+        .iter()
+        .enumerate()
+        .skip(start_index)
+        .map(|(idx, trashed)| {
+            let style = if idx == selected {
+                Style::new().bg(theme.selected).fg(theme.selected_text)
+            } else {
+                Style::new()
+            };
+            Line::from(vec![
+                Span::styled(format!("{:<6}", trashed.task.id), style),
+                Span::styled(trashed.task.desc.clone(), style),
+                Span::styled(
+                    format!("  deleted {}", format_date(trashed.deleted)),
+                    if idx == selected {
+                        style
+                    } else {
+                        style.fg(theme.date)
+                    },
+                ),
+            ])
+        })
+        .collect();
+    f.render_widget(
+        Paragraph::new(if lines.is_empty() {
+            vec![Line::from("Trash is empty")]
+        } else {
+            lines
+        })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.trash))
+                .border_type(BorderType::Rounded)
+                .title("Trash")
+                .padding(Padding::horizontal(1)),
+        ),
+        size,
+    );
+}
+
+/// First row to show so that the selected row stays in a view of `height` rows
+///
+/// The view only moves once the selection leaves it
+fn scroll_offset(offset: usize, selected: Option<usize>, height: usize, len: usize) -> usize {
+    let height = height.max(1);
+    let mut offset = offset.min(len.saturating_sub(height));
+    if let Some(selected) = selected {
+        if selected < offset {
+            offset = selected;
+        } else if selected >= offset + height {
+            offset = selected + 1 - height;
+        }
+    }
+    offset
+}
+
 /// UI when user is neither editing nor exiting a task
-fn render_idle_widget(f: &mut Frame, app: &App, state: &State, size: Rect) {
-    // render all the tasks
-    let mut tasks: Vec<&ListItem> = Vec::new();
+///
+/// Only the rows fitting in the terminal are drawn, with a scrollbar and the
+/// number of rows hidden above and below
+fn render_idle_widget(f: &mut Frame, app: &mut App, state: &State, rows: &[Row], size: Rect) {
+    let theme = &app.theme;
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.tasks))
+        .border_type(BorderType::Rounded)
+        .title("Tasks")
+        .padding(Padding::horizontal(1));
+    let inner = block.inner(size);
+    app.list_area = inner;
+    let height = inner.height as usize;
+    let len = rows.len();
+    let selected = rows.iter().position(|(idx, _)| Some(*idx) == state.cursor);
+    app.page = height.max(1);
+    app.offset = scroll_offset(app.offset, selected, height, len);
 
-    // determine scroll
-    // NOTE: minimum number of rows available for tasks must be 5
+    let above = app.offset;
+    let below = len.saturating_sub(app.offset + height);
+    if above > 0 {
+        block =
+            block.title(Title::from(format!("\u{2191} {above} more")).alignment(Alignment::Right));
+    }
+    if below > 0 {
+        block = block.title(
+            Title::from(format!("\u{2193} {below} more"))
+                .alignment(Alignment::Right)
+                .position(Position::Bottom),
+        );
+    }
+    f.render_widget(block, size);
 
-    if let Some(selected) = state.selected {
-        let start_index = {
-            if selected > 4 {
-                selected - 4
-            } else {
-                0
-            }
+    if len == 0 {
+        let empty = if state.ids.is_empty() {
+            "No tasks yet"
+        } else if let Status::Searching { .. } = app.status {
+            "No matching tasks"
+        } else {
+            "No tasks in this tab"
         };
-        for id in state.ids.iter().skip(start_index) {
-            if let Some(task) = state.tasks.get(id) {
-                tasks.push(task);
-            }
+        f.render_widget(Paragraph::new(empty).fg(theme.placeholder), inner);
+    }
+    for (row, (idx, matched)) in rows.iter().skip(app.offset).take(height).enumerate() {
+        if let Some(item) = state.ids.get(*idx).and_then(|id| state.tasks.get(id)) {
+            let area = Rect {
+                y: inner.y + row as u16,
+                height: 1,
+                ..inner
+            };
+            f.render_widget(
+                ThemedItem {
+                    item,
+                    theme,
+                    matched,
+                    marked: state.marked.contains(&item.task.id),
+                },
+                area,
+            );
         }
-    } else {
-        tasks.extend(state.ids.iter().filter_map(|id| state.tasks.get(id)));
     }
 
-    if let Status::Idle = app.status {
-        let mut state = ListState::default();
+    if len > height {
+        let max_offset = len - height;
+        let mut scrollbar = ScrollbarState::new(max_offset)
+            .viewport_content_length((height * max_offset / len).max(1))
+            .position(app.offset);
         f.render_stateful_widget(
-            List::new(tasks).block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .border_style(Style::default().fg(Color::Green))
-                    .border_type(BorderType::Rounded)
-                    .title("Tasks")
-                    .padding(Padding::horizontal(1)),
-            ),
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None)
+                .style(Style::default().fg(theme.tasks)),
+            size.inner(&Margin {
+                vertical: 1,
+                horizontal: 0,
+            }),
+            &mut scrollbar,
+        );
+    }
+}
+
+/// Everything about the selected task: its dates, tags, notes and the tasks it depends on
+fn render_details_widget(f: &mut Frame, theme: &Theme, state: &State, size: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.border))
+        .border_type(BorderType::Rounded)
+        .title("Details")
+        .padding(Padding::horizontal(1));
+    let Some(task) = state
+        .cursor
+        .and_then(|idx| state.tasks.get(state.ids.get(idx)?))
+        .map(|item| &item.task)
+    else {
+        f.render_widget(
+            Paragraph::new("No task selected")
+                .fg(theme.placeholder)
+                .block(block),
             size,
-            &mut state,
         );
+        return;
+    };
+
+    let label = |name: &str| Span::styled(format!("{name:<11}"), Style::new().fg(theme.date));
+    let field = |name: &str, value: String| Line::from(vec![label(name), Span::raw(value)]);
+    let mut lines = vec![
+        field("ID", task.id.to_string()),
+        Line::from(vec![
+            label("Status"),
+            if task.completed {
+                Span::styled("Completed", Style::new().fg(theme.completed))
+            } else {
+                Span::styled("Pending", Style::new().fg(theme.pending))
+            },
+        ]),
+    ];
+    if let Some(priority) = task.priority {
+        lines.push(field("Priority", priority.to_string()));
     }
+    if let Some(project) = &task.project {
+        lines.push(field("Project", project.clone()));
+    }
+    if !task.tags.is_empty() {
+        let tags: Vec<String> = task.tags.iter().map(|tag| format!("#{tag}")).collect();
+        lines.push(field("Tags", tags.join(" ")));
+    }
+    for (name, date) in [
+        ("Created", task.created),
+        ("Updated", Some(task.last_updated)),
+        ("Completed", task.completed_on),
+        ("Due", task.due),
+    ] {
+        if let Some(date) = date {
+            lines.push(field(name, format_date(date)));
+        }
+    }
+    for (idx, id) in task.depends.iter().enumerate() {
+        let name = if idx == 0 { "Depends on" } else { "" };
+        let dependency = match state.tasks.get(id) {
+            Some(item) if item.task.completed => format!("{id} \u{25ef} {}", item.task.desc),
+            Some(item) => format!("{id} \u{25cf} {}", item.task.desc),
+            // archived or deleted
+            None => format!("{id}"),
+        };
+        lines.push(field(name, dependency));
+    }
+    lines.push(Line::default());
+    lines.extend(task.desc.lines().map(|line| Line::from(line.to_string())));
+    if !task.notes.is_empty() {
+        lines.push(Line::default());
+        lines.push(Line::from(label("Notes")));
+        lines.extend(
+            task.notes
+                .lines()
+                .map(|line| Line::styled(line.to_string(), Style::new().fg(theme.text))),
+        );
+    }
+    f.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(block),
+        size,
+    );
+}
+
+/// Spans of a text drawn with `base`, and with `style` for the chars at the matched indices
+fn highlight<'a>(text: &'a str, matched: &[usize], base: Style, style: Style) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut in_match = false;
+    for (idx, (byte, _)) in text.char_indices().enumerate() {
+        let is_match = matched.contains(&idx);
+        if is_match != in_match {
+            if byte > start {
+                spans.push(Span::styled(
+                    &text[start..byte],
+                    if in_match { style } else { base },
+                ));
+            }
+            start = byte;
+            in_match = is_match;
+        }
+    }
+    if start < text.len() {
+        spans.push(Span::styled(
+            &text[start..],
+            if in_match { style } else { base },
+        ));
+    }
+    spans
 }
 
-pub fn render_list_item(
+fn render_list_item(
     item: &ListItem,
-    area: ratatui::prelude::Rect,
+    theme: &Theme,
+    matched: &[usize],
+    marked: bool,
+    area: Rect,
     buf: &mut ratatui::prelude::Buffer,
 ) {
+    // the cursor is drawn over the background of marked tasks
+    let background = match (item.selected, marked) {
+        (true, _) => theme.selected,
+        (false, true) => theme.marked,
+        (false, false) => Color::default(),
+    };
     let row_layout = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([
-            Constraint::Length(3),
+            Constraint::Length(GLYPH_COLUMN),
             Constraint::Min(30),
             Constraint::Min(5),
         ])
@@ -237,36 +828,47 @@ pub fn render_list_item(
             "\u{25cf}"
         }
     })
+    .fg(if item.task.completed {
+        theme.completed
+    } else {
+        theme.pending
+    })
+    .bg(if marked {
+        theme.marked
+    } else {
+        Color::default()
+    })
     .render(row_layout[0], buf);
-    Paragraph::new(item.task.desc.clone())
-        .bg({
-            if item.selected {
-                Color::LightBlue
-            } else {
-                Color::default()
-            }
-        })
-        .fg({
-            if item.selected {
-                Color::Black
-            } else {
-                Color::default()
-            }
+    let style = Style::new().bg(background).fg(if item.selected {
+        theme.selected_text
+    } else {
+        Color::default()
+    });
+    // the selected row keeps its text color so that matches stay readable
+    let matched_style = style
+        .fg(if item.selected {
+            theme.selected_text
+        } else {
+            theme.matched
         })
+        .add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+    // only the first line of a description fits in the list
+    let mut lines = item.task.desc.lines();
+    let first = lines.next().unwrap_or_default();
+    let mut spans = highlight(first, matched, style, matched_style);
+    if lines.next().is_some() {
+        spans.push(Span::raw(" \u{2026}"));
+    }
+    Paragraph::new(Line::from(spans))
+        .style(style)
         .render(row_layout[1], buf);
     Paragraph::new(format_date(item.task.last_updated))
-        .bg({
-            if item.selected {
-                Color::LightBlue
-            } else {
-                Color::default()
-            }
-        })
+        .bg(background)
         .fg({
             if item.selected {
-                Color::Rgb(25, 25, 25)
+                theme.selected_text
             } else {
-                Color::Rgb(50, 50, 50)
+                theme.date
             }
         })
         .alignment(Alignment::Right)
@@ -277,20 +879,110 @@ pub fn render_list_item(
 pub fn ui(terminal: &mut Term, app: &mut App, state: &State) -> Result<()> {
     terminal.draw(|f| {
         let layout = get_layout().split(f.size());
-        render_status_widget(&app.status, f, layout[0]);
-        match &app.status {
+        let rows = list_rows(app, state);
+        render_status_widget(app, state, f, layout[0]);
+        let theme = &app.theme;
+        match &mut app.status {
             Status::Editing { edit, previous: _ } => {
                 f.render_widget(Clear, f.size());
-                render_editing_widget(f, edit, f.size());
+                render_editing_widget(f, theme, edit, f.size());
+            }
+            Status::Idle | Status::Searching { .. } | Status::Prompt { .. } => {
+                render_tabs_widget(f, app, state, layout[1]);
+                if app.details {
+                    let list_layout = get_list_layout(layout[2]).split(layout[2]);
+                    render_idle_widget(f, app, state, &rows, list_layout[0]);
+                    render_details_widget(f, &app.theme, state, list_layout[1]);
+                } else {
+                    render_idle_widget(f, app, state, &rows, layout[2]);
+                }
             }
-            Status::Idle => {
-                render_idle_widget(f, app, state, layout[1]);
+            Status::Trash { selected } => {
+                render_trash_widget(f, theme, state, *selected, layout[2]);
             }
             Status::Exiting => {
-                render_exiting_widget(f, layout[1]);
+                render_exiting_widget(f, theme, layout[2]);
+            }
+            Status::Conflict { conflicts } => {
+                render_conflict_widget(f, theme, conflicts, layout[2]);
             }
         }
-        render_keymap_widget(&app.status, f, layout[2]);
+        render_keymap_widget(app, f, rows.len(), layout[3]);
     })?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_scroll_offset() {
+        // the view stays put while the selection is inside it
+        assert_eq!(scroll_offset(0, Some(4), 5, 20), 0);
+        assert_eq!(scroll_offset(0, Some(5), 5, 20), 1);
+        assert_eq!(scroll_offset(10, Some(12), 5, 20), 10);
+        assert_eq!(scroll_offset(10, Some(3), 5, 20), 3);
+        // the list shrank below the view
+        assert_eq!(scroll_offset(10, None, 5, 8), 3);
+        assert_eq!(scroll_offset(3, Some(0), 0, 0), 0);
+    }
+
+    #[test]
+    fn check_footer_buttons() {
+        let items = vec![
+            ("e:Edit".to_string(), Action::Edit),
+            ("x:Delete".to_string(), Action::Delete),
+            ("i:New".to_string(), Action::NewTask),
+            ("q:Quit".to_string(), Action::Quit),
+        ];
+        let (lines, buttons) = footer_buttons(items, Rect::new(2, 10, 20, 1));
+        // `e:Edit ｜ x:Delete` takes 18 columns so `i:New` goes on the next line,
+        // which is not clickable as it does not fit
+        assert_eq!(lines.len(), 2);
+        assert_eq!(buttons.len(), 2);
+        assert_eq!(buttons[0], (Rect::new(2, 10, 6, 1), Action::Edit));
+        assert_eq!(buttons[1], (Rect::new(12, 10, 8, 1), Action::Delete));
+    }
+
+    #[test]
+    fn check_details() {
+        let mut dependency = Task {
+            id: 2,
+            desc: "book the venue".to_string(),
+            ..Default::default()
+        };
+        dependency.mark_complete();
+        let task = Task {
+            id: 1,
+            desc: "send invites".to_string(),
+            tags: vec!["party".to_string(), "home".to_string()],
+            notes: "ask about parking".to_string(),
+            depends: vec![2, 9],
+            ..Default::default()
+        };
+        let mut state = State::from_tasks(vec![task, dependency]);
+        state.select(0);
+
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(40, 20)).unwrap();
+        terminal
+            .draw(|f| render_details_widget(f, &Theme::default(), &state, f.size()))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let lines: Vec<String> = (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer.get(x, y).symbol())
+                    .collect()
+            })
+            .collect();
+        let has = |text: &str| lines.iter().any(|line| line.contains(text));
+        assert!(has("Tags       #party #home"));
+        assert!(has("Depends on 2 \u{25ef} book the venue"));
+        // a dependency which is gone only shows its id
+        assert!(has("           9 "));
+        assert!(has("send invites"));
+        assert!(has("Notes"));
+        assert!(has("ask about parking"));
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    app::{App, Status},
+    backup,
+    config::{config, Autosave},
+    files::{data_file, read_stored_data, write_data, CSV_NAME},
+    history::{Operation, Step},
+    oplog::{self, OPS_FOLDER},
+    state::{State, Task},
+    Id, Result,
+};
+
+/// A task changed both in the TUI and in the data file since it was last read
+pub struct Conflict {
+    /// the task in the TUI, `None` if it was deleted there
+    pub ours: Option<Task>,
+    /// the task in the data file, `None` if it was deleted there
+    pub theirs: Option<Task>,
+}
+
+impl Conflict {
+    /// The operation replacing our version of the task with theirs
+    pub fn take_theirs(&self) -> Option<Operation> {
+        change(self.ours.as_ref(), self.theirs.as_ref(), 0)
+    }
+}
+
+/// Operation turning a version of a task into another, `index` is where it is inserted
+fn change(from: Option<&Task>, to: Option<&Task>, index: usize) -> Option<Operation> {
+    match (from, to) {
+        (None, Some(to)) => Some(Operation::Insert {
+            index,
+            task: to.clone(),
+        }),
+        (Some(from), None) => Some(Operation::Remove {
+            index: 0,
+            task: from.clone(),
+        }),
+        (Some(from), Some(to)) if from != to => Some(Operation::update(from.clone(), to.clone())),
+        _ => None,
+    }
+}
+
+/// Changes of the data file which can be applied to the open tasks, along with
+/// the tasks changed on both sides
+///
+/// `base` holds the tasks of the data file when it was last read or written.
+/// A task only changed on one side keeps that change, even a deletion
+pub fn merge(base: &[Task], ours: &State, theirs: &[Task]) -> (Step, Vec<Conflict>) {
+    let base: HashMap<Id, &Task> = base.iter().map(|task| (task.id, task)).collect();
+    let mut seen = HashSet::new();
+    let ids = theirs
+        .iter()
+        .map(|task| task.id)
+        .chain(ours.ids.iter().copied())
+        .chain(base.keys().copied())
+        .filter(|id| seen.insert(*id));
+    let mut step = Vec::new();
+    let mut conflicts = Vec::new();
+    for id in ids {
+        let index = theirs.iter().position(|task| task.id == id);
+        let old = base.get(&id).copied();
+        let new = index.map(|index| &theirs[index]);
+        let current = ours.tasks.get(&id).map(|item| &item.task);
+        if new == old || new == current {
+            continue;
+        }
+        if current == old {
+            step.extend(change(current, new, index.unwrap_or_default()));
+        } else {
+            conflicts.push(Conflict {
+                ours: current.cloned(),
+                theirs: new.cloned(),
+            });
+        }
+    }
+    (step, conflicts)
+}
+
+/// Steps of the stored history which were not in it when it was last read or written
+///
+/// The stored history only grows at its end, but its oldest steps are dropped
+/// once it is full. Nothing is new if the last known step is gone, like after
+/// an undo from the cli
+fn new_steps<'a>(known: &[Step], stored: &'a [Step]) -> &'a [Step] {
+    match known.last() {
+        None => stored,
+        Some(last) => match stored.iter().rposition(|step| step == last) {
+            Some(idx) => &stored[idx + 1..],
+            None => &[],
+        },
+    }
+}
+
+/// Latest modification time of the files holding the tasks
+fn stamp() -> Option<SystemTime> {
+    let mut files = vec![data_file(CSV_NAME)?];
+    // other devices only write their own logs
+    if oplog::enabled() {
+        if let Ok(entries) = fs::read_dir(data_file(OPS_FOLDER)?) {
+            files.extend(entries.filter_map(|entry| Some(entry.ok()?.path())));
+        }
+    }
+    files
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok()?.modified().ok())
+        .max()
+}
+
+/// Least time between two backups taken while saving
+const SNAPSHOT_EVERY: Duration = Duration::from_secs(10 * 60);
+
+/// Keeps the tasks of the TUI in step with the data file
+///
+/// The file is checked while browsing the tasks, changes made to it by the cli
+/// or another device are merged into the open tasks
+pub struct Watcher {
+    /// tasks of the data file when it was last read or written
+    base: Vec<Task>,
+    /// undo steps of the stored history when it was last read or written
+    history: Vec<Step>,
+    /// when a backup was last taken before saving, autosaves would take one per change otherwise
+    snapshot: Option<Instant>,
+    /// when the files holding the tasks were last changed then
+    stamp: Option<SystemTime>,
+    /// revision of the state which was last saved
+    saved: usize,
+    /// last revision of the state along with when it was made, to save once changes pause
+    changed: (usize, Instant),
+}
+
+impl Watcher {
+    /// Start watching from the tasks as they were read from the data file
+    pub fn new(state: &State) -> Self {
+        Self {
+            base: state.get_tasks().into_iter().cloned().collect(),
+            history: state.history.undo.clone(),
+            snapshot: None,
+            stamp: stamp(),
+            saved: state.revision,
+            changed: (state.revision, Instant::now()),
+        }
+    }
+
+    /// Merge the changes made to the data file, then save if autosave is due
+    ///
+    /// Nothing is done unless the task list is shown, so that neither a form
+    /// nor a question is interrupted. Errors are shown in the footer
+    pub fn check(&mut self, app: &mut App, state: &mut State) {
+        if !matches!(app.status, Status::Idle) {
+            return;
+        }
+        if stamp() != self.stamp {
+            if let Err(err) = self.reload(app, state) {
+                app.notice = Some(format!("Could not read the tasks: {err}"));
+                // read again once the file changes, it may have been half written
+                self.stamp = stamp();
+            }
+        }
+        if state.revision != self.changed.0 {
+            self.changed = (state.revision, Instant::now());
+        }
+        let due = match config().storage.autosave() {
+            Autosave::Off => false,
+            Autosave::Change => true,
+            Autosave::Pause(pause) => self.changed.1.elapsed() >= pause,
+        };
+        // the tasks are saved once the conflicts are settled
+        if due && state.revision != self.saved && matches!(app.status, Status::Idle) {
+            if let Err(err) = self.save(state) {
+                app.notice = Some(format!("Could not save the tasks: {err}"));
+                // tried again after the next change
+                self.saved = state.revision;
+            }
+        }
+    }
+
+    /// Write the tasks to the data file, remembering them as its contents
+    ///
+    /// A backup of the data file is taken first, unless one was taken lately
+    pub fn save(&mut self, state: &State) -> Result<()> {
+        if self
+            .snapshot
+            .is_none_or(|taken| taken.elapsed() >= SNAPSHOT_EVERY)
+        {
+            backup::snapshot()?;
+            self.snapshot = Some(Instant::now());
+        }
+        write_data(state, &self.base)?;
+        self.base = state.get_tasks().into_iter().cloned().collect();
+        self.history = state.history.undo.clone();
+        self.stamp = stamp();
+        self.saved = state.revision;
+        Ok(())
+    }
+
+    /// Read the data file again and merge it into the open tasks
+    ///
+    /// Tasks changed on both sides are left for the user to pick a version. The
+    /// steps added to the stored history can be undone here, except for the
+    /// parts touching those tasks
+    fn reload(&mut self, app: &mut App, state: &mut State) -> Result<()> {
+        // files changed while reading are read again
+        self.stamp = stamp();
+        let theirs = read_stored_data()?;
+        let tasks: Vec<Task> = theirs.get_tasks().into_iter().cloned().collect();
+        let (step, conflicts) = merge(&self.base, state, &tasks);
+        state.apply_unrecorded(&step);
+        let conflicting: HashSet<Id> = conflicts
+            .iter()
+            .filter_map(|conflict| Some(conflict.ours.as_ref().or(conflict.theirs.as_ref())?.id))
+            .collect();
+        for new in new_steps(&self.history, &theirs.history.undo) {
+            let ops = new.iter().filter(|op| {
+                let id = match op {
+                    Operation::Insert { task, .. }
+                    | Operation::Remove { task, .. }
+                    | Operation::Trash { task, .. }
+                    | Operation::Restore { task, .. } => task.id,
+                    Operation::Update { after, .. } => after.id,
+                };
+                !conflicting.contains(&id)
+            });
+            state.history.record(ops.cloned().collect());
+        }
+        self.history = theirs.history.undo;
+        for trashed in theirs.trash {
+            let id = trashed.task.id;
+            if !state.tasks.contains_key(&id) && state.trash.iter().all(|old| old.task.id != id) {
+                state.trash.push(trashed);
+            }
+        }
+        // tasks restored on the other side are back in the list
+        let live = &state.tasks;
+        state
+            .trash
+            .retain(|trashed| !live.contains_key(&trashed.task.id));
+        state
+            .trash
+            .sort_by_key(|trashed| std::cmp::Reverse(trashed.deleted));
+        self.base = tasks;
+        if !step.is_empty() {
+            app.notice = Some(match step.len() {
+                1 => "1 task was changed outside, it was reloaded".to_string(),
+                count => format!("{count} tasks were changed outside, they were reloaded"),
+            });
+        }
+        if !conflicts.is_empty() {
+            app.switch_status(Status::Conflict { conflicts });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_merge() {
+        let mut state = State::new();
+        for desc in ["buy milk", "call mom", "fix the build"] {
+            state.add_task(desc);
+        }
+        let base: Vec<Task> = state.get_tasks().into_iter().cloned().collect();
+        let (build, mom, milk) = (base[0].id, base[1].id, base[2].id);
+
+        // the file gains a task and changes another one
+        let mut theirs = base.clone();
+        theirs[2].desc = "buy oat milk".to_string();
+        let added = Task {
+            id: state.unused_id(),
+            desc: "bake bread".to_string(),
+            ..Default::default()
+        };
+        theirs.insert(0, added.clone());
+        // the TUI deletes one and changes another
+        state.remove_tasks(&[mom]);
+        state.edit_task(build, "fix the tests");
+        // both change the same one
+        theirs.retain(|task| task.id != build);
+
+        let (step, conflicts) = merge(&base, &state, &theirs);
+        state.apply_unrecorded(&step);
+        assert_eq!(step.len(), 2);
+        assert_eq!(state.ids[0], added.id);
+        assert_eq!(state.tasks[&milk].task.desc, "buy oat milk");
+        assert!(!state.tasks.contains_key(&mom));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].ours.as_ref().unwrap().desc, "fix the tests");
+        assert!(conflicts[0].theirs.is_none());
+        // only the changes made here can be undone: the additions, the deletion and the edit
+        assert_eq!(state.history.undo.len(), 5);
+
+        // nothing left to merge once both sides agree
+        let (step, conflicts) = merge(&theirs, &state, &theirs);
+        assert!(step.is_empty() && conflicts.is_empty());
+    }
+
+    #[test]
+    fn check_new_steps() {
+        let mut state = State::new();
+        for desc in ["a", "b", "c"] {
+            state.add_task(desc);
+        }
+        let steps = state.history.undo.clone();
+        assert_eq!(new_steps(&[], &steps).len(), 3);
+        assert_eq!(new_steps(&steps[..1], &steps).len(), 2);
+        // the oldest steps were dropped to make room
+        assert_eq!(new_steps(&steps[..2], &steps[1..]).len(), 1);
+        // the last known step was undone elsewhere
+        assert!(new_steps(&steps, &steps[..2]).is_empty());
+    }
+}